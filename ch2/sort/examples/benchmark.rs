@@ -1,8 +1,11 @@
-use num_cpus;
-
-use bitonic_sorter::SortOrder;
+use bitonic_sorter::{SortError, SortOrder};
 use bitonic_sorter::third::sort as seq_sort;
 use bitonic_sorter::fourth::sort as par_sort;
+use bitonic_sorter::simd::sort as simd_sort;
+use bitonic_sorter::scoped::sort as scoped_sort;
+use bitonic_sorter::autotune::autotune;
+use bitonic_sorter::fourth::sort_with_config;
+use bitonic_sorter::sorter::u32_sorters;
 use bitonic_sorter::utils::{is_sorted_ascending, new_u32_vec};
 
 use std::{env, f64};
@@ -16,7 +19,7 @@ fn main() {
     } else {
         eprintln!(
             "Usage {} <number of elements in bits>",
-            env::args().nth(0).unwrap()
+            env::args().next().unwrap()
         );
         std::process::exit(1);
     }
@@ -41,11 +44,46 @@ fn run_sorts(bits: u32) {
     let par_duration = timed_sort(&par_sort, len, "par_sort");
 
     println!("speed up: {:.2}x", seq_duration / par_duration);
+
+    // rayon を使わない std::thread::scope 版
+    let scoped_duration = timed_sort(&scoped_sort, len, "scoped_par_sort");
+    println!("scoped speed up: {:.2}x", seq_duration / scoped_duration);
+
+    // 閾値をこの環境に合わせて選んでから並列にソートする
+    let config = autotune::<u32>();
+    println!("autotune: parallel threshold {}", config.threshold());
+    let tuned_sort = |x: &mut [u32], order: &SortOrder| sort_with_config(x, order, &config);
+    let tuned_duration = timed_sort(&tuned_sort, len, "tuned_par_sort");
+    println!("tuned speed up: {:.2}x", seq_duration / tuned_duration);
+
+    // SIMD 版は1スレッドで動くので、seq_sort と比べる
+    let simd_duration = timed_sort(&simd_sort, len, "simd_sort");
+    println!("simd speed up: {:.2}x", seq_duration / simd_duration);
+
+    // 同じデータで、登録されているすべてのアルゴリズムに順位をつける
+    let mut ranking: Vec<(&str, f64)> = u32_sorters()
+        .into_iter()
+        .map(|sorter| {
+            let sort = |x: &mut [u32], order: &SortOrder| sorter.sort(x, order);
+            (sorter.name(), timed_sort(&sort, len, sorter.name()))
+        })
+        .collect();
+    ranking.push(("fourth (tuned)", tuned_duration));
+    ranking.sort_by(|a, b| a.1.total_cmp(&b.1));
+    println!("ranking:");
+    for (rank, (name, duration)) in ranking.iter().enumerate() {
+        println!(
+            "{:>2}. {:<16} {:.6} seconds",
+            rank + 1,
+            name,
+            duration / 1e9
+        );
+    }
 }
 
 fn timed_sort<F>(sorter: &F, len: usize, name: &str) -> f64
 where
-    F: Fn(&mut [u32], &SortOrder) -> Result<(), String>,
+    F: Fn(&mut [u32], &SortOrder) -> Result<(), SortError>,
 {
     let mut x = new_u32_vec(len);
 
@@ -0,0 +1,143 @@
+use super::fourth::{sort_with_config, SortConfig};
+use super::SortOrder;
+use rand::distributions::{Distribution, Standard};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use rayon::ThreadPoolBuilder;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// fourth の並列化の閾値を、実際にソートして速かったものに決める
+// 速さは要素の型とコア数で変わるので、その組ごとに結果を覚えておく
+
+// 計測に使う要素数の既定値
+pub const SAMPLE_LEN: usize = 1 << 16;
+
+// 閾値の候補ごとに計測する回数。一番速かった回の時間で比べる
+const REPEATS: usize = 3;
+
+// (要素の型, コア数, 要素数) ごとに選んだ設定。計測に使ったスレッドプールも含む
+type Cache = Mutex<HashMap<(TypeId, usize, usize), SortConfig>>;
+
+fn cache() -> &'static Cache {
+    static CACHE: OnceLock<Cache> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// T の要素 SAMPLE_LEN 個で計測した SortConfig を返す。2回目からは計測しない
+pub fn autotune<T>() -> SortConfig
+where
+    T: Send + Ord + 'static,
+    Standard: Distribution<T>,
+{
+    autotune_with_len::<T>(SAMPLE_LEN)
+}
+
+pub fn autotune_with_len<T>(len: usize) -> SortConfig
+where
+    T: Send + Ord + 'static,
+    Standard: Distribution<T>,
+{
+    tune::<T>(len, num_cpus::get())
+}
+
+fn tune<T>(len: usize, cores: usize) -> SortConfig
+where
+    T: Send + Ord + 'static,
+    Standard: Distribution<T>,
+{
+    let key = (TypeId::of::<T>(), cores, len);
+    // 計測中はロックを持たない。同時に呼ばれたら両方が計測し、後の結果が残る
+    let cached = cache().lock().unwrap().get(&key).cloned();
+    match cached {
+        Some(config) => config,
+        None => {
+            let config = measure::<T>(len, cores);
+            cache().lock().unwrap().insert(key, config.clone());
+            config
+        }
+    }
+}
+
+// 256 から4倍ずつ、len 未満の閾値を試す。len は並列化しない場合にあたる
+fn candidates(len: usize) -> Vec<usize> {
+    let mut thresholds: Vec<usize> = std::iter::successors(Some(256), |t| Some(t * 4))
+        .take_while(|&t| t < len)
+        .collect();
+    thresholds.push(len);
+    thresholds
+}
+
+// cores 個のスレッドを持つプールの中で各候補を計測し、そのプールを使う設定を返す
+fn measure<T>(len: usize, cores: usize) -> SortConfig
+where
+    T: Send + Ord,
+    Standard: Distribution<T>,
+{
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(cores)
+        .build()
+        .expect("Failed to build a thread pool");
+    let pool = Arc::new(pool);
+    candidates(len)
+        .into_iter()
+        .map(|threshold| {
+            let config = SortConfig::builder()
+                .threshold(threshold)
+                .thread_pool(Arc::clone(&pool))
+                .build();
+            let best = (0..REPEATS)
+                .map(|_| {
+                    // 毎回同じ並びから始める
+                    let mut x: Vec<T> = Pcg64Mcg::from_seed([0; 16])
+                        .sample_iter(&Standard)
+                        .take(len)
+                        .collect();
+                    let start = Instant::now();
+                    sort_with_config(&mut x, &SortOrder::Ascending, &config)
+                        .expect("Failed to sort");
+                    start.elapsed()
+                })
+                .min()
+                .unwrap_or(Duration::ZERO);
+            (best, config)
+        })
+        // 同じ速さなら小さい閾値を選ぶ
+        .min_by_key(|(best, _)| *best)
+        .map(|(_, config)| config)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        assert_eq!(candidates(100), vec![100]);
+        assert_eq!(candidates(4096), vec![256, 1024, 4096]);
+        assert_eq!(candidates(5000), vec![256, 1024, 4096, 5000]);
+    }
+
+    #[test]
+    fn autotune_small_sample() {
+        let config = autotune_with_len::<u64>(2048);
+        assert!(candidates(2048).contains(&config.threshold()));
+        // 2回目は覚えておいた値を返す
+        assert_eq!(
+            autotune_with_len::<u64>(2048).threshold(),
+            config.threshold()
+        );
+    }
+
+    // 指定したコア数のスレッドプールで計測し、そのプールを設定に残す
+    #[test]
+    fn autotune_uses_core_count() {
+        let config = tune::<u32>(2048, 2);
+        let pool = config.thread_pool().expect("tuned config has a pool");
+        assert_eq!(pool.current_num_threads(), 2);
+        assert!(candidates(2048).contains(&config.threshold()));
+    }
+}
@@ -0,0 +1,217 @@
+#[cfg(feature = "rayon")]
+use super::fourth as parallel;
+#[cfg(not(feature = "rayon"))]
+use super::scoped as parallel;
+use super::{third, SortError, SortOrder};
+use std::cmp::Ordering;
+
+// f32, f64 のスライスをソートする
+// f32, f64 は NaN があるため Ord を実装していないので、比べ方を NanOrder で決める
+
+// NaN の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NanOrder {
+    // IEEE 754 の totalOrder（f64::total_cmp）
+    // 昇順なら -NaN < -inf < ... < -0 < +0 < ... < +inf < +NaN
+    #[default]
+    Total,
+    // 並べる向きにかかわらず NaN を先頭に集める
+    First,
+    // 並べる向きにかかわらず NaN を末尾に集める
+    Last,
+}
+
+// ソートできる浮動小数点数の型。外部のクレートでは実装できない
+pub trait Float: Copy + Send + Sync + private::Sealed {
+    fn total_cmp(&self, other: &Self) -> Ordering;
+    fn is_nan(self) -> bool;
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+impl Float for f32 {
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f32::total_cmp(self, other)
+    }
+    fn is_nan(self) -> bool {
+        f32::is_nan(self)
+    }
+}
+
+impl Float for f64 {
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f64::total_cmp(self, other)
+    }
+    fn is_nan(self) -> bool {
+        f64::is_nan(self)
+    }
+}
+
+// NaN 以外の値どうしは total_cmp で比べるので、-0 は +0 より前に来る
+// NaN どうしも total_cmp で比べる（符号とペイロードで順序が決まる）
+fn comparator<T: Float>(order: &SortOrder, nan: NanOrder) -> impl Fn(&T, &T) -> Ordering + Sync {
+    let forward = matches!(*order, SortOrder::Ascending);
+    move |a: &T, b: &T| {
+        let ordered = if forward {
+            a.total_cmp(b)
+        } else {
+            b.total_cmp(a)
+        };
+        match (nan, a.is_nan(), b.is_nan()) {
+            (NanOrder::First, true, false) | (NanOrder::Last, false, true) => Ordering::Less,
+            (NanOrder::First, false, true) | (NanOrder::Last, true, false) => Ordering::Greater,
+            _ => ordered,
+        }
+    }
+}
+
+// third::sort_by で並べる
+pub fn sort<T: Float>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    sort_with_nan_order(x, order, NanOrder::Total)
+}
+
+pub fn sort_with_nan_order<T: Float>(
+    x: &mut [T],
+    order: &SortOrder,
+    nan: NanOrder,
+) -> Result<(), SortError> {
+    third::sort_by(x, &comparator(order, nan))
+}
+
+// fourth::sort_by（rayon を使わないときは scoped::sort_by）で並列に並べる
+pub fn par_sort<T: Float>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    par_sort_with_nan_order(x, order, NanOrder::Total)
+}
+
+pub fn par_sort_with_nan_order<T: Float>(
+    x: &mut [T],
+    order: &SortOrder,
+    nan: NanOrder,
+) -> Result<(), SortError> {
+    parallel::sort_by(x, &comparator(order, nan))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+    use crate::SortOrder::*;
+
+    // NaN どうしや ±0 を区別するため、ビット列で比べる
+    fn bits(x: &[f64]) -> Vec<u64> {
+        x.iter().map(|x| x.to_bits()).collect()
+    }
+
+    fn special_values() -> Vec<f64> {
+        vec![
+            1.5,
+            f64::NAN,
+            -0.0,
+            f64::NEG_INFINITY,
+            0.0,
+            -f64::NAN,
+            f64::INFINITY,
+            -2.0,
+            f64::MIN_POSITIVE,
+        ]
+    }
+
+    #[test]
+    fn sort_f64_total_order() {
+        let expected = [
+            -f64::NAN,
+            f64::NEG_INFINITY,
+            -2.0,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.5,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+
+        let mut x = special_values();
+        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert_eq!(bits(&x), bits(&expected));
+
+        let mut x = special_values();
+        assert_eq!(par_sort(&mut x, &Descending), Ok(()));
+        let mut reversed = expected;
+        reversed.reverse();
+        assert_eq!(bits(&x), bits(&reversed));
+    }
+
+    #[test]
+    fn sort_f64_nan_first_and_last() {
+        let mut x = special_values();
+        assert_eq!(
+            sort_with_nan_order(&mut x, &Descending, NanOrder::First),
+            Ok(())
+        );
+        let expected = [
+            f64::NAN,
+            -f64::NAN,
+            f64::INFINITY,
+            1.5,
+            f64::MIN_POSITIVE,
+            0.0,
+            -0.0,
+            -2.0,
+            f64::NEG_INFINITY,
+        ];
+        assert_eq!(bits(&x), bits(&expected));
+
+        let mut x = special_values();
+        assert_eq!(
+            par_sort_with_nan_order(&mut x, &Ascending, NanOrder::Last),
+            Ok(())
+        );
+        let expected = [
+            f64::NEG_INFINITY,
+            -2.0,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.5,
+            f64::INFINITY,
+            -f64::NAN,
+            f64::NAN,
+        ];
+        assert_eq!(bits(&x), bits(&expected));
+    }
+
+    // 乱数のビット列をそのまま f32 にすると NaN や非正規化数も含まれる
+    #[test]
+    fn sort_f32_random_bits() {
+        for &n in &[0, 1, 7, 300, 100_000] {
+            let x: Vec<f32> = refined_new_u32_vec(n)
+                .into_iter()
+                .map(f32::from_bits)
+                .collect();
+            let mut expected = x.clone();
+            expected.sort_by(f32::total_cmp);
+            let expected: Vec<u32> = expected.iter().map(|x| x.to_bits()).collect();
+
+            let mut y = x.clone();
+            assert_eq!(sort(&mut y, &Ascending), Ok(()));
+            assert_eq!(y.iter().map(|x| x.to_bits()).collect::<Vec<_>>(), expected);
+
+            let mut y = x.clone();
+            assert_eq!(par_sort(&mut y, &Ascending), Ok(()));
+            assert_eq!(y.iter().map(|x| x.to_bits()).collect::<Vec<_>>(), expected);
+
+            let mut y = x.clone();
+            assert_eq!(
+                par_sort_with_nan_order(&mut y, &Ascending, NanOrder::Last),
+                Ok(())
+            );
+            let nans = y.iter().rev().take_while(|x| x.is_nan()).count();
+            assert_eq!(nans, x.iter().filter(|x| x.is_nan()).count());
+            assert!(y[..y.len() - nans].windows(2).all(|w| w[0] <= w[1]));
+        }
+    }
+}
@@ -1,8 +1,11 @@
-use super::SortOrder;
+use super::{apply_permutation, catch_comparator_panic, SortError, SortOrder};
 use rayon;
+use rayon::prelude::*;
+use rayon::ThreadPool;
 use std::cmp::Ordering;
+use std::sync::Arc;
 
-pub fn sort<T: Send + Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+pub fn sort<T: Send + Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
     match *order {
         SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
         SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
@@ -11,16 +14,102 @@ pub fn sort<T: Send + Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String>
 
 const PARALLEL_THRESHOLD: usize = 4096;
 
+// 並列化の設定。SortConfig::builder().threshold(1 << 16).max_depth(3).build() のように組み立てる
+#[derive(Debug, Clone)]
+pub struct SortConfig {
+    // 分割した片方の長さがこれを超えるときだけ並列に処理する
+    threshold: usize,
+    // 並列に分割する深さの上限（None なら上限なし）
+    max_depth: Option<usize>,
+    // None なら rayon のグローバルなスレッドプールを使う
+    pool: Option<Arc<ThreadPool>>,
+    // true なら並列化せず、呼び出したスレッドだけでソートする
+    sequential: bool,
+}
+
+impl Default for SortConfig {
+    fn default() -> Self {
+        SortConfig {
+            threshold: PARALLEL_THRESHOLD,
+            max_depth: None,
+            pool: None,
+            sequential: false,
+        }
+    }
+}
+
+impl SortConfig {
+    pub fn builder() -> SortConfigBuilder {
+        SortConfigBuilder::default()
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn thread_pool(&self) -> Option<&Arc<ThreadPool>> {
+        self.pool.as_ref()
+    }
+
+    pub fn is_sequential(&self) -> bool {
+        self.sequential
+    }
+
+    // 深さ depth で長さ mid_point ずつに分割するとき、並列に処理するかどうか
+    fn parallel(&self, mid_point: usize, depth: usize) -> bool {
+        !self.sequential
+            && mid_point > self.threshold
+            && self.max_depth.is_none_or(|max_depth| depth < max_depth)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SortConfigBuilder {
+    config: SortConfig,
+}
+
+impl SortConfigBuilder {
+    pub fn threshold(mut self, threshold: usize) -> Self {
+        self.config.threshold = threshold;
+        self
+    }
+
+    // 深さ d までなら同時に動くタスクは最大 2^d 個になる
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.config.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.config.pool = Some(pool);
+        self
+    }
+
+    pub fn sequential(mut self, sequential: bool) -> Self {
+        self.config.sequential = sequential;
+        self
+    }
+
+    pub fn build(self) -> SortConfig {
+        self.config
+    }
+}
+
 // 並行処理を行う際、スレッド間で共有することができるデータの型には Send トレイトを実装する必要がある
 // また、共有されるクロージャは Sync トレイトを実装している必要がある
-fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
+fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F, config: &SortConfig, depth: usize)
 where
     T: Send,
     F: Sync + Fn(&T, &T) -> Ordering,
 {
     if x.len() > 1 {
+        // 前半を逆順、後半を forward の順にソートすると、長さが2のべき乗でなくてもバイトニック列になる
         let mid_point = x.len() / 2;
-        if mid_point > PARALLEL_THRESHOLD {
+        if config.parallel(mid_point, depth) {
             // 所有権の問題、ある値に対する可変の参照を同時に複数持つことはできない
             // x[..mid_point] と x[mid_point..] に対してそれぞれ可変の参照を持つことはできない
             // ので、スライスをコピーする
@@ -32,40 +121,43 @@ where
             // このように書き換える
             let (first, second) = x.split_at_mut(mid_point);
             rayon::join(
-                || do_sort(first, true, comparator),
-                || do_sort(second, false, comparator),
+                || do_sort(first, !forward, comparator, config, depth + 1),
+                || do_sort(second, forward, comparator, config, depth + 1),
             );
         } else {
-            do_sort(&mut x[..mid_point], true, comparator);
-            do_sort(&mut x[mid_point..], false, comparator);
+            do_sort(&mut x[..mid_point], !forward, comparator, config, depth);
+            do_sort(&mut x[mid_point..], forward, comparator, config, depth);
         }
-        sub_sort(x, forward, comparator);
+        sub_sort(x, forward, comparator, config, depth);
     }
 }
 
-fn sub_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
+fn sub_sort<T, F>(x: &mut [T], forward: bool, comparator: &F, config: &SortConfig, depth: usize)
 where
     T: Send,
     F: Sync + Fn(&T, &T) -> Ordering,
 {
     if x.len() > 1 {
-        compare_and_swap(x, forward, comparator);
-        let mid_point = x.len() / 2;
+        // len 未満の最大の2のべき乗で区切る。末尾に仮想的な値を補って
+        // 2のべき乗の長さにしたときの、補った値との比較（交換は起きない）を省いたものになる
+        let mid_point = 1 << (x.len() - 1).ilog2();
+        compare_and_swap(x, mid_point, forward, comparator);
         let (first, second) = x.split_at_mut(mid_point);
 
-        if mid_point > PARALLEL_THRESHOLD {
+        if config.parallel(mid_point, depth) {
             rayon::join(
-                || sub_sort(first, forward, comparator),
-                || sub_sort(second, forward, comparator),
+                || sub_sort(first, forward, comparator, config, depth + 1),
+                || sub_sort(second, forward, comparator, config, depth + 1),
             );
         } else {
-            sub_sort(&mut x[..mid_point], forward, comparator);
-            sub_sort(&mut x[mid_point..], forward, comparator);
+            sub_sort(&mut x[..mid_point], forward, comparator, config, depth);
+            sub_sort(&mut x[mid_point..], forward, comparator, config, depth);
         }
     }
 }
 
-fn compare_and_swap<T, F>(x: &mut [T], forward: bool, comparator: &F)
+// x[i] と x[mid_point + i] を比較する。x の長さは mid_point の2倍以下
+fn compare_and_swap<T, F>(x: &mut [T], mid_point: usize, forward: bool, comparator: &F)
 where
     F: Fn(&T, &T) -> Ordering,
 {
@@ -75,26 +167,193 @@ where
         Ordering::Less
     };
 
-    let mid_point = x.len() / 2;
-    for i in 0..mid_point {
+    for i in 0..x.len() - mid_point {
         if (comparator(&x[i], &x[mid_point + i])) == swap_condition {
             x.swap(i, mid_point + i);
         }
     }
 }
 
-pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), String>
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
 where
     T: Send,
     F: Sync + Fn(&T, &T) -> Ordering,
 {
-    if x.len().is_power_of_two() {
-        do_sort(x, true, comparator);
-        Ok(())
-    } else {
-        Err(format!(
-            "The length of x is not a power of two. (x.len(): {})",
-            x.len()
-        ))
+    sort_by_with_config(x, comparator, &SortConfig::default())
+}
+
+pub fn sort_with_config<T: Send + Ord>(
+    x: &mut [T],
+    order: &SortOrder,
+    config: &SortConfig,
+) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending => sort_by_with_config(x, &|a, b| a.cmp(b), config),
+        SortOrder::Descending => sort_by_with_config(x, &|a, b| b.cmp(a), config),
+    }
+}
+
+// スレッドプールが指定されていれば、その中でソートする
+pub fn sort_by_with_config<T, F>(
+    x: &mut [T],
+    comparator: &F,
+    config: &SortConfig,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    catch_comparator_panic(|| match &config.pool {
+        Some(pool) if !config.sequential => {
+            pool.install(|| do_sort(x, true, comparator, config, 0))
+        }
+        _ => do_sort(x, true, comparator, config, 0),
+    })
+}
+
+// 要素から取り出したキーで比較する。キーは比較のたびに計算する
+pub fn sort_by_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+where
+    T: Send,
+    K: Ord,
+    F: Sync + Fn(&T) -> K,
+{
+    sort_by(x, &|a, b| key(a).cmp(&key(b)))
+}
+
+// キーを要素ごとに1回だけ（並列に）計算してからソートする
+// キーが等しい要素の順序は保たれる
+pub fn sort_by_cached_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+where
+    T: Send + Sync,
+    K: Send + Ord,
+    F: Sync + Fn(&T) -> K,
+{
+    let mut keys = vec![];
+    catch_comparator_panic(|| {
+        keys = x.par_iter().enumerate().map(|(i, v)| (key(v), i)).collect();
+    })?;
+    sort_by(&mut keys, &|a, b| a.cmp(b))?;
+    let mut order: Vec<usize> = keys.into_iter().map(|(_, i)| i).collect();
+    apply_permutation(x, &mut order);
+    Ok(())
+}
+
+// 等しい要素の順序を保つソート
+// バイトニックソートは安定ではないので、添字を並べ替え、比較が等しいときは元の位置で比べる
+pub fn stable_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    T: Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let mut order: Vec<usize> = (0..x.len()).collect();
+    sort_by(&mut order, &|&i, &j| {
+        comparator(&x[i], &x[j]).then(i.cmp(&j))
+    })?;
+    apply_permutation(x, &mut order);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+    use crate::SortOrder::*;
+
+    // 上位の桁だけで比べ、下位の桁で元の順序を確かめる
+    #[test]
+    fn stable_sort_u32_large() {
+        let x: Vec<u32> = refined_new_u32_vec(100_000)
+            .iter()
+            .map(|v| v % 1_000_000)
+            .collect();
+        let mut expected = x.clone();
+        expected.sort_by_key(|v| v / 1000);
+
+        let mut y = x.clone();
+        assert_eq!(
+            stable_sort_by(&mut y, &|a, b| (a / 1000).cmp(&(b / 1000))),
+            Ok(())
+        );
+        assert_eq!(y, expected);
+
+        let mut y = x.clone();
+        assert_eq!(sort_by_cached_key(&mut y, &|v| v / 1000), Ok(()));
+        assert_eq!(y, expected);
+
+        let mut y = x.clone();
+        assert_eq!(sort_by_key(&mut y, &|v| std::cmp::Reverse(*v)), Ok(()));
+        assert!(is_sorted_descending(&y));
+    }
+
+    // 指定したスレッドプールの中で比較が行われる
+    #[test]
+    fn sort_with_thread_pool() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .thread_name(|i| format!("sorter-{}", i))
+            .build()
+            .unwrap();
+        let config = SortConfig::builder()
+            .threshold(256)
+            .max_depth(2)
+            .thread_pool(Arc::new(pool))
+            .build();
+
+        let mut x = refined_new_u32_vec(10_000);
+        let result = sort_by_with_config(
+            &mut x,
+            &|a: &u32, b: &u32| {
+                let name = std::thread::current().name().map(String::from);
+                assert!(name.unwrap_or_default().starts_with("sorter-"));
+                a.cmp(b)
+            },
+            &config,
+        );
+        assert_eq!(result, Ok(()));
+        assert!(is_sorted_ascending(&x));
+    }
+
+    // sequential なら呼び出したスレッドだけでソートする
+    #[test]
+    fn sort_with_sequential_config() {
+        let caller = std::thread::current().id();
+        let config = SortConfig::builder().threshold(1).sequential(true).build();
+
+        let mut x = refined_new_u32_vec(10_000);
+        let result = sort_by_with_config(
+            &mut x,
+            &|a: &u32, b: &u32| {
+                assert_eq!(std::thread::current().id(), caller);
+                b.cmp(a)
+            },
+            &config,
+        );
+        assert_eq!(result, Ok(()));
+        assert!(is_sorted_descending(&x));
+
+        for config in [
+            SortConfig::builder().max_depth(0).build(),
+            SortConfig::builder().threshold(0).build(),
+        ] {
+            let mut x = refined_new_u32_vec(10_000);
+            assert_eq!(sort_with_config(&mut x, &Ascending, &config), Ok(()));
+            assert!(is_sorted_ascending(&x));
+        }
+    }
+
+    // PARALLEL_THRESHOLD を超えて rayon で分割される長さ
+    #[test]
+    fn sort_u32_large() {
+        {
+            let mut x = refined_new_u32_vec(100_000);
+            assert_eq!(sort(&mut x, &Ascending), Ok(()));
+            assert!(is_sorted_ascending(&x));
+        }
+        {
+            let mut x = refined_new_u32_vec(100_000);
+            assert_eq!(sort(&mut x, &Descending), Ok(()));
+            assert!(is_sorted_descending(&x));
+        }
     }
 }
@@ -0,0 +1,54 @@
+use super::{catch_comparator_panic, SortError, SortOrder};
+use std::cmp::Ordering;
+
+// ヒープソート。追加のメモリを使わず、最悪でも O(n log n) で終わる
+// quick のイントロソートが再帰の深くなりすぎた区間に使う
+
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    catch_comparator_panic(|| heap_sort(x, comparator))
+}
+
+// comparator で最も後ろに来る要素を根に持つヒープを作り、根を末尾と入れ替えていく
+pub(crate) fn heap_sort<T, F>(x: &mut [T], comparator: &F)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    for i in (0..x.len() / 2).rev() {
+        sift_down(x, i, comparator);
+    }
+    for end in (1..x.len()).rev() {
+        x.swap(0, end);
+        sift_down(&mut x[..end], 0, comparator);
+    }
+}
+
+// i 番目の要素を、子のほうが後ろに来る間だけ下ろしていく
+fn sift_down<T, F>(x: &mut [T], mut i: usize, comparator: &F)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    loop {
+        let mut child = 2 * i + 1;
+        if child >= x.len() {
+            break;
+        }
+        if child + 1 < x.len() && comparator(&x[child], &x[child + 1]) == Ordering::Less {
+            child += 1;
+        }
+        if comparator(&x[i], &x[child]) != Ordering::Less {
+            break;
+        }
+        x.swap(i, child);
+        i = child;
+    }
+}
@@ -1,10 +1,77 @@
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
 pub enum SortOrder {
     Ascending,
     Descending,
 }
 
+// ソート関数が返すエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortError {
+    // 比較関数（または Ord::cmp）がパニックした。スライスは並べ替えの途中のまま残る
+    ComparatorPanicked(Option<String>),
+    // 比較関数を受け取れないアルゴリズムに比較関数を渡した（Sorter::sort_by）
+    ComparatorUnsupported(&'static str),
+}
+
+impl fmt::Display for SortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SortError::ComparatorPanicked(Some(msg)) => {
+                write!(f, "the comparator panicked: {}", msg)
+            }
+            SortError::ComparatorPanicked(None) => write!(f, "the comparator panicked"),
+            SortError::ComparatorUnsupported(name) => {
+                write!(f, "{} does not support custom comparators", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SortError {}
+
+// ソート中のパニックを SortError にする
+// ソートは要素を入れ替えるだけなので、途中でパニックしてもスライスは元の要素の並べ替えになっている
+pub(crate) fn catch_comparator_panic<F: FnOnce()>(f: F) -> Result<(), SortError> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .map_err(|payload| SortError::ComparatorPanicked(panic_message(payload)))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> Option<String> {
+    match payload.downcast::<String>() {
+        Ok(msg) => Some(*msg),
+        Err(payload) => payload.downcast_ref::<&str>().map(|msg| msg.to_string()),
+    }
+}
+
+// order[i] 番目にあった要素が i 番目に来るように、x をその場で並べ替える（order は壊れる）
+// 入れ替え先がすでに動かした位置なら、そこへ動いた先をたどる
+pub(crate) fn apply_permutation<T>(x: &mut [T], order: &mut [usize]) {
+    for i in 0..x.len() {
+        let mut index = order[i];
+        while index < i {
+            index = order[index];
+        }
+        order[i] = index;
+        x.swap(i, index);
+    }
+}
+
+#[cfg(feature = "rayon")]
+pub mod autotune;
+pub mod float;
+#[cfg(feature = "rayon")]
 pub mod fourth;
+pub mod heap;
+pub mod merge;
 pub mod multi_thread;
+pub mod quick;
+pub mod radix;
+pub mod scoped;
 pub mod second;
+pub mod simd;
+pub mod sorter;
 pub mod third;
 pub mod utils;
@@ -0,0 +1,142 @@
+use super::{catch_comparator_panic, SortError, SortOrder};
+use std::cmp::Ordering;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+// 並列マージソート。安定で、最悪でも O(n log n) だが、x と同じ長さの作業領域を使う
+// 左右の半分を std::thread::scope で並列にソートし、作業領域とのあいだで交互にマージする
+// マージは要素を複製するので、比較関数がパニックしたら書きかけの側を他方の並びで戻す
+
+const PARALLEL_THRESHOLD: usize = 4096;
+const INSERTION_THRESHOLD: usize = 16;
+
+pub fn sort<T>(x: &mut [T], order: &SortOrder) -> Result<(), SortError>
+where
+    T: Send + Sync + Clone + Ord,
+{
+    match *order {
+        SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    T: Send + Sync + Clone,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    // workers 個のスレッドに行き渡るまで分割する
+    let depth = workers.next_power_of_two().ilog2();
+    let mut buf = x.to_vec();
+    catch_comparator_panic(|| sort_into(x, &mut buf, comparator, depth))
+}
+
+// dst と src に同じ並びが入っているときに、ソートした結果を dst に書く
+// 左右の半分を役割を入れ替えて src 側にソートし、それを dst にマージする
+fn sort_into<T, F>(dst: &mut [T], src: &mut [T], comparator: &F, depth: u32)
+where
+    T: Send + Sync + Clone,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if dst.len() <= INSERTION_THRESHOLD {
+        insertion_sort(dst, comparator);
+        return;
+    }
+    // パニックで抜けるときも、src は元の並べ替えになっている（下の段が同じことをするため）
+    // dst は書きかけなので src で上書きして、並べ替えの状態に戻してから伝える
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mid_point = dst.len() / 2;
+        let (dst_left, dst_right) = dst.split_at_mut(mid_point);
+        let (src_left, src_right) = src.split_at_mut(mid_point);
+        if depth > 0 && mid_point > PARALLEL_THRESHOLD {
+            thread::scope(|s| {
+                s.spawn(|| sort_into(src_left, dst_left, comparator, depth - 1));
+                sort_into(src_right, dst_right, comparator, depth - 1);
+            });
+        } else {
+            sort_into(src_left, dst_left, comparator, 0);
+            sort_into(src_right, dst_right, comparator, 0);
+        }
+        merge(src_left, src_right, dst, comparator);
+    }));
+    if let Err(payload) = result {
+        dst.clone_from_slice(src);
+        panic::resume_unwind(payload);
+    }
+}
+
+// 等しい要素は左を先に取るので安定になる
+fn merge<T, F>(left: &[T], right: &[T], dst: &mut [T], comparator: &F)
+where
+    T: Clone,
+    F: Fn(&T, &T) -> Ordering,
+{
+    let (mut i, mut j) = (0, 0);
+    for slot in dst.iter_mut() {
+        let take_left = j == right.len()
+            || (i < left.len() && comparator(&left[i], &right[j]) != Ordering::Greater);
+        if take_left {
+            slot.clone_from(&left[i]);
+            i += 1;
+        } else {
+            slot.clone_from(&right[j]);
+            j += 1;
+        }
+    }
+}
+
+fn insertion_sort<T, F>(x: &mut [T], comparator: &F)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    for i in 1..x.len() {
+        let mut j = i;
+        while j > 0 && comparator(&x[j - 1], &x[j]) == Ordering::Greater {
+            x.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+
+    // 上位の桁だけで比べ、下位の桁で元の順序が保たれていることを確かめる
+    #[test]
+    fn sort_u32_large_stable() {
+        let x: Vec<u32> = refined_new_u32_vec(100_000)
+            .iter()
+            .map(|v| v % 1_000_000)
+            .collect();
+        let mut expected = x.clone();
+        expected.sort_by_key(|v| v / 1000);
+
+        let mut y = x.clone();
+        assert_eq!(sort_by(&mut y, &|a, b| (a / 1000).cmp(&(b / 1000))), Ok(()));
+        assert_eq!(y, expected);
+    }
+
+    #[test]
+    fn sort_comparator_panicked() {
+        let x = refined_new_u32_vec(100_000);
+        let poison = x[50_000];
+
+        let mut y = x.clone();
+        let result = sort_by(&mut y, &|a: &u32, b: &u32| {
+            if *a == poison || *b == poison {
+                panic!("poisoned value");
+            }
+            a.cmp(b)
+        });
+        // 別のスレッドでパニックすると、メッセージは thread::scope のものになる
+        assert!(matches!(result, Err(SortError::ComparatorPanicked(_))));
+        // 途中まで複製した要素が残らず、元の要素の並べ替えになっている
+        let mut expected = x.clone();
+        expected.sort();
+        y.sort();
+        assert_eq!(y, expected);
+    }
+}
@@ -0,0 +1,117 @@
+use super::heap::heap_sort;
+use super::{catch_comparator_panic, SortError, SortOrder};
+use std::cmp::Ordering;
+
+// イントロソート。クイックソートの再帰が深くなりすぎた区間はヒープソートに切り替えるので、
+// 最悪でも O(n log n) で終わる。短い区間は挿入ソートで並べる
+
+const INSERTION_THRESHOLD: usize = 16;
+
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    // 再帰の深さの上限は 2 log2(n)
+    let limit = 2 * (x.len() | 1).ilog2();
+    catch_comparator_panic(|| intro_sort(x, comparator, limit))
+}
+
+fn intro_sort<T, F>(mut x: &mut [T], comparator: &F, mut limit: u32)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    while x.len() > INSERTION_THRESHOLD {
+        if limit == 0 {
+            heap_sort(x, comparator);
+            return;
+        }
+        limit -= 1;
+
+        let pivot = partition(x, comparator);
+        // 短いほうを再帰で、長いほうをループで処理して、スタックの深さを抑える
+        let (left, right) = x.split_at_mut(pivot);
+        let right = &mut right[1..];
+        if left.len() < right.len() {
+            intro_sort(left, comparator, limit);
+            x = right;
+        } else {
+            intro_sort(right, comparator, limit);
+            x = left;
+        }
+    }
+    insertion_sort(x, comparator);
+}
+
+// 先頭・中央・末尾の中央値を軸にして分割し、軸の位置を返す
+// 軸より前は軸以下、後ろは軸以上になる
+fn partition<T, F>(x: &mut [T], comparator: &F) -> usize
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let last = x.len() - 1;
+    let mid = x.len() / 2;
+    if comparator(&x[mid], &x[0]) == Ordering::Less {
+        x.swap(mid, 0);
+    }
+    if comparator(&x[last], &x[0]) == Ordering::Less {
+        x.swap(last, 0);
+    }
+    if comparator(&x[last], &x[mid]) == Ordering::Less {
+        x.swap(last, mid);
+    }
+    // 軸を末尾に置き、それより前を分ける
+    x.swap(mid, last);
+
+    let mut store = 0;
+    for i in 0..last {
+        if comparator(&x[i], &x[last]) == Ordering::Less {
+            x.swap(i, store);
+            store += 1;
+        }
+    }
+    x.swap(store, last);
+    store
+}
+
+fn insertion_sort<T, F>(x: &mut [T], comparator: &F)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    for i in 1..x.len() {
+        let mut j = i;
+        while j > 0 && comparator(&x[j - 1], &x[j]) == Ordering::Greater {
+            x.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+    use crate::SortOrder::*;
+
+    // 同じ値ばかりの入力や、ソート済みの入力でも遅くならない
+    #[test]
+    fn sort_u32_adversarial() {
+        let mut x = vec![7_u32; 100_000];
+        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert!(x.iter().all(|&v| v == 7));
+
+        let mut x: Vec<u32> = (0..100_000).collect();
+        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        assert!(is_sorted_descending(&x));
+
+        let mut x: Vec<u32> = refined_new_u32_vec(100_000).iter().map(|v| v % 4).collect();
+        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert!(is_sorted_ascending(&x));
+    }
+}
@@ -0,0 +1,101 @@
+use super::{SortError, SortOrder};
+
+// LSD 基数ソート。整数を下位の 8 ビットから順に、数え上げソートで安定に並べていく
+// 比較を使わないので O(n * バイト数) で終わるが、x と同じ長さの作業領域を使う
+
+pub fn sort<T: RadixKey>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    let descending = matches!(*order, SortOrder::Descending);
+    let mut buf = x.to_vec();
+    // src から dst へ1桁ずつ並べ替え、そのたびに役割を入れ替える
+    let (mut src, mut dst) = (&mut *x, &mut buf[..]);
+    let mut swapped = false;
+    for byte in 0..T::BYTES {
+        let digit = |v: &T| {
+            let key = v.key();
+            // 降順はキーを反転して昇順に並べる
+            let key = if descending { !key } else { key };
+            (key >> (8 * byte)) as u8 as usize
+        };
+
+        let mut counts = [0usize; 256];
+        src.iter().for_each(|v| counts[digit(v)] += 1);
+        // この桁がすべて同じなら並びは変わらない
+        if counts.contains(&src.len()) {
+            continue;
+        }
+
+        let mut offsets = [0usize; 256];
+        for d in 1..256 {
+            offsets[d] = offsets[d - 1] + counts[d - 1];
+        }
+        for v in src.iter() {
+            let d = digit(v);
+            dst[offsets[d]] = *v;
+            offsets[d] += 1;
+        }
+        std::mem::swap(&mut src, &mut dst);
+        swapped = !swapped;
+    }
+    if swapped {
+        x.copy_from_slice(&buf);
+    }
+    Ok(())
+}
+
+// 基数ソートできる整数の型。大小関係を保ったまま符号なしの整数に変換する
+pub trait RadixKey: Copy {
+    const BYTES: usize;
+    fn key(self) -> u64;
+}
+
+macro_rules! impl_radix_key {
+    // 符号なし整数はそのまま
+    (unsigned $($t:ty),*) => {$(
+        impl RadixKey for $t {
+            const BYTES: usize = std::mem::size_of::<$t>();
+            fn key(self) -> u64 {
+                self as u64
+            }
+        }
+    )*};
+    // 符号付き整数は符号ビットを反転すると、符号なしとして比べても大小関係が変わらない
+    (signed $($t:ty => $u:ty),*) => {$(
+        impl RadixKey for $t {
+            const BYTES: usize = std::mem::size_of::<$t>();
+            fn key(self) -> u64 {
+                (self as $u ^ (1 << (<$u>::BITS - 1))) as u64
+            }
+        }
+    )*};
+}
+
+impl_radix_key!(unsigned u8, u16, u32, u64, usize);
+impl_radix_key!(signed i8 => u8, i16 => u16, i32 => u32, i64 => u64, isize => usize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+    use crate::SortOrder::*;
+
+    #[test]
+    fn sort_signed_and_narrow() {
+        let mut x: Vec<i64> = vec![3, -1, i64::MIN, 0, i64::MAX, -1000, 42];
+        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert_eq!(x, vec![i64::MIN, -1000, -1, 0, 3, 42, i64::MAX]);
+
+        let mut x: Vec<i8> = vec![5, -128, 127, 0, -3];
+        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        assert_eq!(x, vec![127, 5, 0, -3, -128]);
+
+        // 上位のバイトがすべて同じ（0）でも正しく並ぶ
+        let mut x: Vec<u64> = refined_new_u32_vec(1000)
+            .iter()
+            .map(|&v| v as u64)
+            .collect();
+        let mut expected = x.clone();
+        expected.sort();
+        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert_eq!(x, expected);
+    }
+}
@@ -0,0 +1,184 @@
+use super::{catch_comparator_panic, SortError, SortOrder};
+use std::cmp::Ordering;
+use std::panic;
+use std::thread;
+
+// fourth と同じ並列バイトニックソートを、rayon を使わず std::thread::scope で行う
+// スレッドは分割のたびに必要な分だけ立ち上げ、同時に動くスレッドの数は workers 以下に抑える
+
+const PARALLEL_THRESHOLD: usize = 4096;
+
+pub fn sort<T: Send + Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+// 論理コアの数だけのスレッドでソートする
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    sort_by_with_workers(x, comparator, workers)
+}
+
+// 呼び出したスレッドも含めて、最大 workers 個のスレッドでソートする（0 は 1 とみなす）
+pub fn sort_by_with_workers<T, F>(
+    x: &mut [T],
+    comparator: &F,
+    workers: usize,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    catch_comparator_panic(|| do_sort(x, true, comparator, workers.max(1)))
+}
+
+// first を新しいスレッドで、second をこのスレッドで処理する
+// workers のうち半分を新しいスレッドに、残りをこのスレッドに割り当てる
+fn join<T, F, G>(first: &mut [T], second: &mut [T], workers: usize, f: &F, g: &G)
+where
+    T: Send,
+    F: Sync + Fn(&mut [T], usize),
+    G: Sync + Fn(&mut [T], usize),
+{
+    let spawned = workers / 2;
+    thread::scope(|s| {
+        let handle = s.spawn(move || f(first, spawned));
+        g(second, workers - spawned);
+        // スレッドのパニックは、元のメッセージのままこのスレッドに伝える
+        if let Err(payload) = handle.join() {
+            panic::resume_unwind(payload);
+        }
+    });
+}
+
+fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F, workers: usize)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if x.len() > 1 {
+        // 前半を逆順、後半を forward の順にソートすると、長さが2のべき乗でなくてもバイトニック列になる
+        let mid_point = x.len() / 2;
+        if workers > 1 && mid_point > PARALLEL_THRESHOLD {
+            let (first, second) = x.split_at_mut(mid_point);
+            join(
+                first,
+                second,
+                workers,
+                &|x, workers| do_sort(x, !forward, comparator, workers),
+                &|x, workers| do_sort(x, forward, comparator, workers),
+            );
+        } else {
+            do_sort(&mut x[..mid_point], !forward, comparator, 1);
+            do_sort(&mut x[mid_point..], forward, comparator, 1);
+        }
+        sub_sort(x, forward, comparator, workers);
+    }
+}
+
+fn sub_sort<T, F>(x: &mut [T], forward: bool, comparator: &F, workers: usize)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if x.len() > 1 {
+        // len 未満の最大の2のべき乗で区切る
+        let mid_point = 1 << (x.len() - 1).ilog2();
+        compare_and_swap(x, mid_point, forward, comparator);
+        let (first, second) = x.split_at_mut(mid_point);
+
+        if workers > 1 && mid_point > PARALLEL_THRESHOLD {
+            let f = |x: &mut [T], workers| sub_sort(x, forward, comparator, workers);
+            join(first, second, workers, &f, &f);
+        } else {
+            sub_sort(first, forward, comparator, 1);
+            sub_sort(second, forward, comparator, 1);
+        }
+    }
+}
+
+// x[i] と x[mid_point + i] を比較する。x の長さは mid_point の2倍以下
+fn compare_and_swap<T, F>(x: &mut [T], mid_point: usize, forward: bool, comparator: &F)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let swap_condition = if forward {
+        Ordering::Greater
+    } else {
+        Ordering::Less
+    };
+
+    for i in 0..x.len() - mid_point {
+        if (comparator(&x[i], &x[mid_point + i])) == swap_condition {
+            x.swap(i, mid_point + i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+    use std::sync::Mutex;
+
+    // 同時に比較を行っているスレッドの数が workers を超えない
+    #[test]
+    fn sort_u32_bounded_workers() {
+        for workers in [0, 1, 3, 4] {
+            let running = AtomicUsize::new(0);
+            let peak = AtomicUsize::new(0);
+            let threads = Mutex::new(std::collections::HashSet::new());
+
+            let mut x = refined_new_u32_vec(100_000);
+            let result = sort_by_with_workers(
+                &mut x,
+                &|a: &u32, b: &u32| {
+                    let now = running.fetch_add(1, SeqCst) + 1;
+                    peak.fetch_max(now, SeqCst);
+                    threads.lock().unwrap().insert(thread::current().id());
+                    running.fetch_sub(1, SeqCst);
+                    a.cmp(b)
+                },
+                workers,
+            );
+            assert_eq!(result, Ok(()));
+            assert!(is_sorted_ascending(&x));
+            assert!(peak.load(SeqCst) <= workers.max(1));
+            if workers <= 1 {
+                assert_eq!(threads.lock().unwrap().len(), 1);
+            }
+        }
+    }
+
+    // 新しく立ち上げたスレッドでパニックしても、元のメッセージのまま返る
+    // 前半は新しいスレッドが受け持つので、毒は先頭の値にする
+    #[test]
+    fn sort_comparator_panicked_in_spawned_thread() {
+        let mut x = refined_new_u32_vec(100_000);
+        let poison = x[0];
+
+        let result = sort_by_with_workers(
+            &mut x,
+            &|a: &u32, b: &u32| {
+                if *a == poison || *b == poison {
+                    panic!("poisoned value");
+                }
+                a.cmp(b)
+            },
+            4,
+        );
+        assert_eq!(
+            result,
+            Err(SortError::ComparatorPanicked(Some(
+                "poisoned value".to_string()
+            )))
+        );
+    }
+}
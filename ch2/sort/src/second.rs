@@ -1,18 +1,11 @@
-use super::SortOrder;
-
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
-    if x.len().is_power_of_two() {
-        match *order {
-            SortOrder::Ascending => do_sort(x, true),
-            SortOrder::Descending => do_sort(x, false),
-        };
-        Ok(())
-    } else {
-        Err(format!(
-            "The length of x is not a power of two. (x.len(): {})",
-            x.len()
-        ))
-    }
+use super::{catch_comparator_panic, SortError, SortOrder};
+
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    let up = match *order {
+        SortOrder::Ascending => true,
+        SortOrder::Descending => false,
+    };
+    catch_comparator_panic(|| do_sort(x, up))
 }
 
 // pub はこのモジュールが外部に公開されることを示す
@@ -20,25 +13,30 @@ pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
 fn do_sort<T: Ord>(x: &mut [T], up: bool) {
     //&mut は値をポインタ経由で「可変」「借用」することを示す、コピーせず代入して使用できる
     if x.len() > 1 {
+        // 長さが2のべき乗でなくてもいいように、前半を逆順、後半を up の順にソートする
+        // 2のべき乗のときは前半・後半のどちらを逆順にしてもバイトニック列になる
         let mid_point = x.len() / 2;
-        do_sort(&mut x[..mid_point], true);
-        do_sort(&mut x[mid_point..], false);
+        do_sort(&mut x[..mid_point], !up);
+        do_sort(&mut x[mid_point..], up);
         sub_sort(x, up)
     }
 }
 
 fn sub_sort<T: Ord>(x: &mut [T], up: bool) {
     if x.len() > 1 {
-        compare_and_swap(x, up);
-        let mid_point = x.len() / 2;
+        // 長さを超えない最大の2のべき乗（len 未満）で区切る
+        // 末尾に up の向きで最も後ろに来る値を補って2のべき乗の長さにしたと考えると、
+        // 補った値との比較では交換が起きないので、その比較を省いたものと同じになる
+        let mid_point = 1 << (x.len() - 1).ilog2();
+        compare_and_swap(x, mid_point, up);
         sub_sort(&mut x[..mid_point], up);
         sub_sort(&mut x[mid_point..], up);
     }
 }
 
-fn compare_and_swap<T: Ord>(x: &mut [T], up: bool) {
-    let mid_point = x.len() / 2;
-    for i in 0..mid_point {
+// x[i] と x[mid_point + i] を比較する。x の長さは mid_point の2倍以下
+fn compare_and_swap<T: Ord>(x: &mut [T], mid_point: usize, up: bool) {
+    for i in 0..x.len() - mid_point {
         // この比較でコケるので、T が Ord トレイトを実装していることを明示
         // Ord は比較演算子を使える型に実装されるトレイト
         // PartialOrd とは異なり、全順序関係を持つ（全ての値が比較可能） PartialOrd は一部の値（NaN）が比較不可能
@@ -134,10 +132,10 @@ mod tests {
     }
 
     #[test]
-    fn sort_to_fail() {
+    fn sort_u32_odd_length() {
         let mut x: Vec<u32> = vec![10, 30, 11];
-        let expected = sort(&mut x, &Ascending);
 
-        assert!(expected.is_err());
+        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert_eq!(x, vec![10, 11, 30]);
     }
 }
@@ -0,0 +1,449 @@
+use super::{SortError, SortOrder};
+
+// u32, i32, f32, u64 のスライスを SIMD 命令でソートする
+// 比較・交換（compare_and_swap）は x[i..] と x[mid_point + i..] の連続した範囲どうしなので、
+// 8個（u64 は4個）ずつまとめて min / max を取れる
+// 8個以下の小さなブロックは1つのレジスタに読み込み、レジスタの中でソートする
+//
+// i32, f32 は大小関係を保ったまま u32 に変換してからソートし、最後に元に戻す
+// f32 の順序は f32::total_cmp と同じ（-NaN < -inf < ... < -0 < +0 < ... < +inf < +NaN）
+pub fn sort<T: SimdKey>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    let up = match *order {
+        SortOrder::Ascending => true,
+        SortOrder::Descending => false,
+    };
+    T::sort_slice(x, up);
+    Ok(())
+}
+
+// SIMD でソートできる型。外部のクレートでは実装できない
+pub trait SimdKey: Copy + private::Sealed {}
+
+impl SimdKey for u32 {}
+impl SimdKey for i32 {}
+impl SimdKey for f32 {}
+impl SimdKey for u64 {}
+
+mod private {
+    use super::{Isa, Keys};
+
+    // 命令セットはここで調べるので、クレートの外から呼ばれても対応していない命令は使わない
+    pub trait Sealed: Sized {
+        fn sort_slice(x: &mut [Self], up: bool);
+    }
+
+    impl<T: Keys> Sealed for T {
+        fn sort_slice(x: &mut [T], up: bool) {
+            T::sort_keys(x, up, Isa::detect());
+        }
+    }
+}
+
+// 使う命令セット。実行時に CPU が対応しているものを調べる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Isa {
+    Avx2,
+    Sse2,
+    Scalar,
+}
+
+impl Isa {
+    fn detect() -> Isa {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Isa::Avx2;
+            }
+            if is_x86_feature_detected!("sse2") {
+                return Isa::Sse2;
+            }
+        }
+        Isa::Scalar
+    }
+}
+
+const SIGN_BIT: u32 = 1 << 31;
+
+// 指定した命令セットでソートする。isa は CPU が対応しているものでなければならない
+pub(crate) trait Keys: Copy {
+    fn sort_keys(x: &mut [Self], up: bool, isa: Isa);
+}
+
+impl Keys for u32 {
+    fn sort_keys(x: &mut [u32], up: bool, isa: Isa) {
+        do_sort(x, up, isa);
+    }
+}
+
+impl Keys for u64 {
+    fn sort_keys(x: &mut [u64], up: bool, isa: Isa) {
+        do_sort(x, up, isa);
+    }
+}
+
+impl Keys for i32 {
+    fn sort_keys(x: &mut [i32], up: bool, isa: Isa) {
+        // 符号ビットを反転すると、符号なしとして比べたときも大小関係が変わらない
+        let keys = as_u32_slice(x);
+        keys.iter_mut().for_each(|k| *k ^= SIGN_BIT);
+        do_sort(keys, up, isa);
+        keys.iter_mut().for_each(|k| *k ^= SIGN_BIT);
+    }
+}
+
+impl Keys for f32 {
+    fn sort_keys(x: &mut [f32], up: bool, isa: Isa) {
+        // 負の数は全ビットを反転、正の数は符号ビットだけを立てると total_cmp の順になる
+        let keys = as_u32_slice(x);
+        keys.iter_mut().for_each(|k| {
+            *k = if *k & SIGN_BIT != 0 {
+                !*k
+            } else {
+                *k | SIGN_BIT
+            };
+        });
+        do_sort(keys, up, isa);
+        keys.iter_mut().for_each(|k| {
+            *k = if *k & SIGN_BIT != 0 {
+                *k & !SIGN_BIT
+            } else {
+                !*k
+            };
+        });
+    }
+}
+
+// i32, f32 のスライスを同じ場所にある u32 のスライスとして見る（コピーはしない）
+fn as_u32_slice<T: Keys>(x: &mut [T]) -> &mut [u32] {
+    assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<u32>());
+    assert_eq!(std::mem::align_of::<T>(), std::mem::align_of::<u32>());
+    // 大きさとアラインメントが同じで、どのビット列も u32 として有効なので安全
+    unsafe { std::slice::from_raw_parts_mut(x.as_mut_ptr() as *mut u32, x.len()) }
+}
+
+// SIMD でソートする整数の型ごとの処理
+trait Kernel: Copy + Ord {
+    // 1つの AVX2 レジスタに入る個数
+    const LANES: usize;
+
+    // a[i] と b[i] を比較して交換する。処理しきれなかった端数はスカラーで処理する
+    fn compare_and_swap(a: &mut [Self], b: &mut [Self], up: bool, isa: Isa);
+
+    // LANES 個以下の要素をレジスタの中でソートする（AVX2 のときだけ呼ばれる）
+    fn sort_block(x: &mut [Self], up: bool);
+}
+
+impl Kernel for u32 {
+    const LANES: usize = 8;
+
+    fn compare_and_swap(a: &mut [u32], b: &mut [u32], up: bool, isa: Isa) {
+        let done = match isa {
+            #[cfg(target_arch = "x86_64")]
+            Isa::Avx2 => unsafe { x86::compare_and_swap_u32_avx2(a, b, up) },
+            #[cfg(target_arch = "x86_64")]
+            Isa::Sse2 => unsafe { x86::compare_and_swap_u32_sse2(a, b, up) },
+            _ => 0,
+        };
+        scalar_compare_and_swap(&mut a[done..], &mut b[done..], up);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn sort_block(x: &mut [u32], up: bool) {
+        unsafe { x86::sort_block_u32_avx2(x, up) }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn sort_block(_x: &mut [u32], _up: bool) {
+        unreachable!("sort_block is only called with Isa::Avx2, which is detected only on x86_64")
+    }
+}
+
+impl Kernel for u64 {
+    const LANES: usize = 4;
+
+    fn compare_and_swap(a: &mut [u64], b: &mut [u64], up: bool, isa: Isa) {
+        // SSE2 には 64 ビット整数の比較がないので、AVX2 のときだけ SIMD を使う
+        let done = match isa {
+            #[cfg(target_arch = "x86_64")]
+            Isa::Avx2 => unsafe { x86::compare_and_swap_u64_avx2(a, b, up) },
+            _ => 0,
+        };
+        scalar_compare_and_swap(&mut a[done..], &mut b[done..], up);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn sort_block(x: &mut [u64], up: bool) {
+        unsafe { x86::sort_block_u64_avx2(x, up) }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn sort_block(_x: &mut [u64], _up: bool) {
+        unreachable!("sort_block is only called with Isa::Avx2, which is detected only on x86_64")
+    }
+}
+
+// second::do_sort と同じ、長さが2のべき乗でなくてもよいバイトニックソート
+fn do_sort<K: Kernel>(x: &mut [K], up: bool, isa: Isa) {
+    if isa == Isa::Avx2 && x.len() <= K::LANES {
+        K::sort_block(x, up);
+    } else if x.len() > 1 {
+        let mid_point = x.len() / 2;
+        do_sort(&mut x[..mid_point], !up, isa);
+        do_sort(&mut x[mid_point..], up, isa);
+        sub_sort(x, up, isa);
+    }
+}
+
+fn sub_sort<K: Kernel>(x: &mut [K], up: bool, isa: Isa) {
+    // バイトニック列をマージした結果はソートした結果と同じなので、小さなブロックはそのままソートする
+    if isa == Isa::Avx2 && x.len() <= K::LANES {
+        K::sort_block(x, up);
+    } else if x.len() > 1 {
+        let mid_point = 1 << (x.len() - 1).ilog2();
+        let (first, second) = x.split_at_mut(mid_point);
+        let n = second.len();
+        K::compare_and_swap(&mut first[..n], second, up, isa);
+        sub_sort(&mut x[..mid_point], up, isa);
+        sub_sort(&mut x[mid_point..], up, isa);
+    }
+}
+
+fn scalar_compare_and_swap<K: Kernel>(a: &mut [K], b: &mut [K], up: bool) {
+    for (a, b) in a.iter_mut().zip(b.iter_mut()) {
+        if (*a > *b) == up {
+            std::mem::swap(a, b);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    // 以下の関数は、呼び出す前に CPU が命令セットに対応していることを確かめておく必要がある
+    // compare_and_swap_* は処理した要素の数（レジスタの幅の倍数）を返す
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn compare_and_swap_u32_avx2(a: &mut [u32], b: &mut [u32], up: bool) -> usize {
+        let n = a.len() / 8 * 8;
+        for i in (0..n).step_by(8) {
+            let pa = a.as_mut_ptr().add(i) as *mut __m256i;
+            let pb = b.as_mut_ptr().add(i) as *mut __m256i;
+            let va = _mm256_loadu_si256(pa);
+            let vb = _mm256_loadu_si256(pb);
+            let (lo, hi) = (_mm256_min_epu32(va, vb), _mm256_max_epu32(va, vb));
+            let (ra, rb) = if up { (lo, hi) } else { (hi, lo) };
+            _mm256_storeu_si256(pa, ra);
+            _mm256_storeu_si256(pb, rb);
+        }
+        n
+    }
+
+    // SSE2 には符号なしの比較も 32 ビットの min / max もないので、
+    // 符号ビットを反転して符号付きで比較し、マスクで選ぶ
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn compare_and_swap_u32_sse2(a: &mut [u32], b: &mut [u32], up: bool) -> usize {
+        let sign = _mm_set1_epi32(i32::MIN);
+        let n = a.len() / 4 * 4;
+        for i in (0..n).step_by(4) {
+            let pa = a.as_mut_ptr().add(i) as *mut __m128i;
+            let pb = b.as_mut_ptr().add(i) as *mut __m128i;
+            let va = _mm_loadu_si128(pa);
+            let vb = _mm_loadu_si128(pb);
+            let gt = _mm_cmpgt_epi32(_mm_xor_si128(va, sign), _mm_xor_si128(vb, sign));
+            let lo = _mm_or_si128(_mm_and_si128(gt, vb), _mm_andnot_si128(gt, va));
+            let hi = _mm_or_si128(_mm_and_si128(gt, va), _mm_andnot_si128(gt, vb));
+            let (ra, rb) = if up { (lo, hi) } else { (hi, lo) };
+            _mm_storeu_si128(pa, ra);
+            _mm_storeu_si128(pb, rb);
+        }
+        n
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn min_max_u64(a: __m256i, b: __m256i) -> (__m256i, __m256i) {
+        let sign = _mm256_set1_epi64x(i64::MIN);
+        let gt = _mm256_cmpgt_epi64(_mm256_xor_si256(a, sign), _mm256_xor_si256(b, sign));
+        (_mm256_blendv_epi8(a, b, gt), _mm256_blendv_epi8(b, a, gt))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn compare_and_swap_u64_avx2(a: &mut [u64], b: &mut [u64], up: bool) -> usize {
+        let n = a.len() / 4 * 4;
+        for i in (0..n).step_by(4) {
+            let pa = a.as_mut_ptr().add(i) as *mut __m256i;
+            let pb = b.as_mut_ptr().add(i) as *mut __m256i;
+            let (lo, hi) = min_max_u64(_mm256_loadu_si256(pa), _mm256_loadu_si256(pb));
+            let (ra, rb) = if up { (lo, hi) } else { (hi, lo) };
+            _mm256_storeu_si256(pa, ra);
+            _mm256_storeu_si256(pb, rb);
+        }
+        n
+    }
+
+    // 8個以下の u32 をレジスタの中でバイトニックソートする
+    // 足りない分は、ソートすると末尾に来る値で埋めておき、最後に捨てる
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sort_block_u32_avx2(x: &mut [u32], up: bool) {
+        let mut buf = [if up { u32::MAX } else { u32::MIN }; 8];
+        buf[..x.len()].copy_from_slice(x);
+        let mut v = _mm256_loadu_si256(buf.as_ptr() as *const __m256i);
+
+        let lane = _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7);
+        let zero = _mm256_setzero_si256();
+        let flip = if up { zero } else { _mm256_set1_epi32(-1) };
+        let mut k = 2;
+        while k <= 8 {
+            let mut j = k / 2;
+            while j > 0 {
+                // 各レーンは i ^ j 番目のレーンと比較する
+                let partner =
+                    _mm256_permutevar8x32_epi32(v, _mm256_xor_si256(lane, _mm256_set1_epi32(j)));
+                let (lo, hi) = (_mm256_min_epu32(v, partner), _mm256_max_epu32(v, partner));
+                // 昇順に並べる区間の前側のレーンと、降順に並べる区間の後側のレーンが小さい方を取る
+                let front = _mm256_cmpeq_epi32(_mm256_and_si256(lane, _mm256_set1_epi32(j)), zero);
+                let asc = _mm256_cmpeq_epi32(_mm256_and_si256(lane, _mm256_set1_epi32(k)), zero);
+                let take_max = _mm256_xor_si256(front, _mm256_xor_si256(asc, flip));
+                v = _mm256_blendv_epi8(lo, hi, take_max);
+                j /= 2;
+            }
+            k *= 2;
+        }
+
+        _mm256_storeu_si256(buf.as_mut_ptr() as *mut __m256i, v);
+        x.copy_from_slice(&buf[..x.len()]);
+    }
+
+    // 4個以下の u64 をレジスタの中でバイトニックソートする
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sort_block_u64_avx2(x: &mut [u64], up: bool) {
+        let mut buf = [if up { u64::MAX } else { u64::MIN }; 4];
+        buf[..x.len()].copy_from_slice(x);
+        let mut v = _mm256_loadu_si256(buf.as_ptr() as *const __m256i);
+
+        let lane = _mm256_setr_epi64x(0, 1, 2, 3);
+        // 64 ビットのレーン i は 32 ビットのレーン 2i, 2i + 1 にあたる
+        let lane32 = _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7);
+        let zero = _mm256_setzero_si256();
+        let flip = if up { zero } else { _mm256_set1_epi64x(-1) };
+        let mut k = 2;
+        while k <= 4 {
+            let mut j = k / 2;
+            while j > 0 {
+                let index = _mm256_xor_si256(lane32, _mm256_set1_epi32(2 * j as i32));
+                let partner = _mm256_permutevar8x32_epi32(v, index);
+                let (lo, hi) = min_max_u64(v, partner);
+                let front = _mm256_cmpeq_epi64(_mm256_and_si256(lane, _mm256_set1_epi64x(j)), zero);
+                let asc = _mm256_cmpeq_epi64(_mm256_and_si256(lane, _mm256_set1_epi64x(k)), zero);
+                let take_max = _mm256_xor_si256(front, _mm256_xor_si256(asc, flip));
+                v = _mm256_blendv_epi8(lo, hi, take_max);
+                j /= 2;
+            }
+            k *= 2;
+        }
+
+        _mm256_storeu_si256(buf.as_mut_ptr() as *mut __m256i, v);
+        x.copy_from_slice(&buf[..x.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+    use crate::SortOrder::*;
+
+    // CPU が対応している命令セットをすべて試す
+    fn available_isas() -> Vec<Isa> {
+        let mut isas = vec![Isa::Scalar];
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse2") {
+                isas.push(Isa::Sse2);
+            }
+            if is_x86_feature_detected!("avx2") {
+                isas.push(Isa::Avx2);
+            }
+        }
+        isas
+    }
+
+    fn check<T, F>(x: Vec<T>, up: bool, cmp: F)
+    where
+        T: Keys + std::fmt::Debug,
+        F: Fn(&T, &T) -> std::cmp::Ordering,
+    {
+        let mut expected = x.clone();
+        expected.sort_by(|a, b| if up { cmp(a, b) } else { cmp(b, a) });
+        for isa in available_isas() {
+            let mut y = x.clone();
+            T::sort_keys(&mut y, up, isa);
+            assert!(
+                y.iter().zip(&expected).all(|(a, b)| cmp(a, b).is_eq()),
+                "{:?} len: {}",
+                isa,
+                x.len()
+            );
+        }
+    }
+
+    #[test]
+    fn sort_every_length() {
+        for n in 0..=300 {
+            let v = refined_new_u32_vec(n);
+            for up in [true, false] {
+                check(v.clone(), up, u32::cmp);
+                check(v.iter().map(|&x| x as i32).collect(), up, i32::cmp);
+                check(
+                    v.iter().map(|&x| f32::from_bits(x)).collect(),
+                    up,
+                    f32::total_cmp,
+                );
+                check(
+                    v.iter()
+                        .map(|&x| (x as u64) << 32 | x.rotate_left(7) as u64)
+                        .collect(),
+                    up,
+                    u64::cmp,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sort_f32_special_values() {
+        let mut x = vec![
+            1.0,
+            f32::NAN,
+            -0.0,
+            f32::NEG_INFINITY,
+            0.0,
+            -f32::NAN,
+            f32::INFINITY,
+            -1.5,
+        ];
+        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        let bits: Vec<u32> = x.iter().map(|x| x.to_bits()).collect();
+        let expected: Vec<u32> = [
+            -f32::NAN,
+            f32::NEG_INFINITY,
+            -1.5,
+            -0.0,
+            0.0,
+            1.0,
+            f32::INFINITY,
+            f32::NAN,
+        ]
+        .iter()
+        .map(|x| x.to_bits())
+        .collect();
+        assert_eq!(bits, expected);
+    }
+
+    #[test]
+    fn sort_u32_large() {
+        let mut x = refined_new_u32_vec(100_000);
+        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        assert!(is_sorted_descending(&x));
+    }
+}
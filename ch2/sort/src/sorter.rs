@@ -0,0 +1,238 @@
+#[cfg(feature = "rayon")]
+use super::fourth;
+use super::radix::RadixKey;
+use super::simd::SimdKey;
+use super::{heap, merge, quick, radix, scoped, second, simd, third, SortError, SortOrder};
+use std::cmp::Ordering;
+
+// ソートのアルゴリズムを同じ形で扱うためのトレイト
+// モジュールごとに大きさ0の型を用意し、sorters() でまとめて取り出せるようにする
+
+pub type Comparator<'a, T> = dyn Fn(&T, &T) -> Ordering + Sync + 'a;
+
+pub trait Sorter<T> {
+    fn name(&self) -> &'static str;
+
+    // 等しい要素の順序を保つか
+    fn is_stable(&self) -> bool;
+
+    // 複数のスレッドを使うか
+    fn is_parallel(&self) -> bool;
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError>;
+
+    // 比較関数で並べる。比較関数を受け取れないアルゴリズムは ComparatorUnsupported を返す
+    fn sort_by(&self, _x: &mut [T], _comparator: &Comparator<T>) -> Result<(), SortError> {
+        Err(SortError::ComparatorUnsupported(self.name()))
+    }
+}
+
+// 比較関数を受け取るモジュールの Sorter を実装する
+macro_rules! impl_sorter {
+    ($(#[$attr:meta])* $ty:ident, $module:ident, $name:expr, stable: $stable:expr, parallel: $parallel:expr, $($bound:tt)*) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct $ty;
+
+        $(#[$attr])*
+        impl<T: $($bound)*> Sorter<T> for $ty {
+            fn name(&self) -> &'static str {
+                $name
+            }
+            fn is_stable(&self) -> bool {
+                $stable
+            }
+            fn is_parallel(&self) -> bool {
+                $parallel
+            }
+            fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+                $module::sort(x, order)
+            }
+            fn sort_by(&self, x: &mut [T], comparator: &Comparator<T>) -> Result<(), SortError> {
+                $module::sort_by(x, &comparator)
+            }
+        }
+    };
+}
+
+impl_sorter!(Third, third, "third", stable: false, parallel: false, Ord);
+impl_sorter!(
+    #[cfg(feature = "rayon")]
+    Fourth, fourth, "fourth", stable: false, parallel: true, Send + Ord
+);
+impl_sorter!(Scoped, scoped, "scoped", stable: false, parallel: true, Send + Ord);
+impl_sorter!(Merge, merge, "merge", stable: true, parallel: true, Send + Sync + Clone + Ord);
+impl_sorter!(Quick, quick, "quick", stable: false, parallel: false, Ord);
+impl_sorter!(Heap, heap, "heap", stable: false, parallel: false, Ord);
+
+// second は Ord で比べる版だけなので、sort_by は使えない
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Second;
+
+impl<T: Ord> Sorter<T> for Second {
+    fn name(&self) -> &'static str {
+        "second"
+    }
+    fn is_stable(&self) -> bool {
+        false
+    }
+    fn is_parallel(&self) -> bool {
+        false
+    }
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        second::sort(x, order)
+    }
+}
+
+// 基数ソートは比較を使わない
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Radix;
+
+impl<T: RadixKey> Sorter<T> for Radix {
+    fn name(&self) -> &'static str {
+        "radix"
+    }
+    fn is_stable(&self) -> bool {
+        true
+    }
+    fn is_parallel(&self) -> bool {
+        false
+    }
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        radix::sort(x, order)
+    }
+}
+
+// SIMD 版は値そのものを並べ替えるので、等しい値を区別できない
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Simd;
+
+impl<T: SimdKey> Sorter<T> for Simd {
+    fn name(&self) -> &'static str {
+        "simd"
+    }
+    fn is_stable(&self) -> bool {
+        false
+    }
+    fn is_parallel(&self) -> bool {
+        false
+    }
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        simd::sort(x, order)
+    }
+}
+
+// Ord を実装した任意の型をソートできるもの
+pub fn sorters<T>() -> Vec<&'static dyn Sorter<T>>
+where
+    T: Send + Sync + Clone + Ord,
+{
+    vec![
+        &Second,
+        &Third,
+        #[cfg(feature = "rayon")]
+        &Fourth,
+        &Scoped,
+        &Merge,
+        &Quick,
+        &Heap,
+    ]
+}
+
+// u32 をソートできるものすべて（基数ソートと SIMD 版を含む）
+pub fn u32_sorters() -> Vec<&'static dyn Sorter<u32>> {
+    let mut all = sorters::<u32>();
+    all.push(&Radix);
+    all.push(&Simd);
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+    use crate::SortOrder::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn sorter_names_are_unique() {
+        let names: HashSet<_> = u32_sorters().iter().map(|s| s.name()).collect();
+        assert_eq!(names.len(), u32_sorters().len());
+    }
+
+    // どのアルゴリズムも、長さが2のべき乗でなくてもソートできる
+    #[test]
+    fn sort_u32_every_length() {
+        for sorter in u32_sorters() {
+            for n in 0..=300 {
+                let mut expected = refined_new_u32_vec(n);
+                expected.sort();
+
+                let mut x = refined_new_u32_vec(n);
+                assert_eq!(sorter.sort(&mut x, &Ascending), Ok(()));
+                assert_eq!(x, expected, "{} len: {}", sorter.name(), n);
+
+                let mut x = refined_new_u32_vec(n);
+                assert_eq!(sorter.sort(&mut x, &Descending), Ok(()));
+                expected.reverse();
+                assert_eq!(x, expected, "{} len: {}", sorter.name(), n);
+            }
+        }
+    }
+
+    // 比較関数がパニックしてもエラーとして返り、スライスの要素は失われない
+    // 並列版でも呼び出したスレッドが最初に毒に触れるよう、毒は末尾の値にする
+    #[test]
+    fn sort_by_comparator_panicked() {
+        let x = refined_new_u32_vec(100_000);
+        let poison = x[x.len() - 1];
+        let mut expected = x.clone();
+        expected.sort();
+
+        for sorter in sorters::<u32>() {
+            let mut y = x.clone();
+            let result = sorter.sort_by(&mut y, &|a: &u32, b: &u32| {
+                if *a == poison || *b == poison {
+                    panic!("poisoned value");
+                }
+                a.cmp(b)
+            });
+            match result {
+                Err(SortError::ComparatorUnsupported(name)) => assert_eq!(name, sorter.name()),
+                result => assert_eq!(
+                    result.map_err(|e| e.to_string()),
+                    Err("the comparator panicked: poisoned value".to_string()),
+                    "{}",
+                    sorter.name()
+                ),
+            }
+            y.sort();
+            assert_eq!(y, expected, "{}", sorter.name());
+        }
+    }
+
+    // 比較関数を受け取るものは、安定と称するなら等しい要素の順序を保つ
+    #[test]
+    fn sort_by_and_stability() {
+        let x: Vec<(u32, usize)> = refined_new_u32_vec(10_000)
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v % 100, i))
+            .collect();
+        let mut expected = x.clone();
+        expected.sort_by_key(|p| p.0);
+
+        for sorter in sorters::<(u32, usize)>() {
+            let mut y = x.clone();
+            match sorter.sort_by(&mut y, &|a, b| a.0.cmp(&b.0)) {
+                Ok(()) => {
+                    assert!(y.windows(2).all(|w| w[0].0 <= w[1].0), "{}", sorter.name());
+                    if sorter.is_stable() {
+                        assert_eq!(y, expected, "{}", sorter.name());
+                    }
+                }
+                Err(e) => assert_eq!(e, SortError::ComparatorUnsupported(sorter.name())),
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use super::SortOrder;
+use super::{apply_permutation, catch_comparator_panic, SortError, SortOrder};
 use std::cmp::Ordering;
 
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
     match *order {
         SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
         SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
@@ -13,9 +13,10 @@ where
     F: Fn(&T, &T) -> Ordering,
 {
     if x.len() > 1 {
+        // 前半を逆順、後半を forward の順にソートすると、長さが2のべき乗でなくてもバイトニック列になる
         let mid_point = x.len() / 2;
-        do_sort(&mut x[..mid_point], true, comparator);
-        do_sort(&mut x[mid_point..], false, comparator);
+        do_sort(&mut x[..mid_point], !forward, comparator);
+        do_sort(&mut x[mid_point..], forward, comparator);
         sub_sort(x, forward, comparator);
     }
 }
@@ -25,14 +26,17 @@ where
     F: Fn(&T, &T) -> Ordering,
 {
     if x.len() > 1 {
-        compare_and_swap(x, forward, comparator);
-        let mid_point = x.len() / 2;
+        // len 未満の最大の2のべき乗で区切る。末尾に仮想的な値を補って
+        // 2のべき乗の長さにしたときの、補った値との比較（交換は起きない）を省いたものになる
+        let mid_point = 1 << (x.len() - 1).ilog2();
+        compare_and_swap(x, mid_point, forward, comparator);
         sub_sort(&mut x[..mid_point], forward, comparator);
         sub_sort(&mut x[mid_point..], forward, comparator);
     }
 }
 
-fn compare_and_swap<T, F>(x: &mut [T], forward: bool, comparator: &F)
+// x[i] と x[mid_point + i] を比較する。x の長さは mid_point の2倍以下
+fn compare_and_swap<T, F>(x: &mut [T], mid_point: usize, forward: bool, comparator: &F)
 where
     F: Fn(&T, &T) -> Ordering,
 {
@@ -43,27 +47,59 @@ where
         Ordering::Less
     };
 
-    let mid_point = x.len() / 2;
-    for i in 0..mid_point {
+    for i in 0..x.len() - mid_point {
         if (comparator(&x[i], &x[mid_point + i])) == swap_condition {
             x.swap(i, mid_point + i);
         }
     }
 }
 
-pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), String>
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
 where
     F: Fn(&T, &T) -> Ordering,
 {
-    if x.len().is_power_of_two() {
-        do_sort(x, true, comparator);
-        Ok(())
-    } else {
-        Err(format!(
-            "The length of x is not a power of two. (x.len(): {})",
-            x.len()
-        ))
-    }
+    catch_comparator_panic(|| do_sort(x, true, comparator))
+}
+
+// 要素から取り出したキーで比較する。キーは比較のたびに計算する
+pub fn sort_by_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+where
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    sort_by(x, &|a, b| key(a).cmp(&key(b)))
+}
+
+// キーを要素ごとに1回だけ計算してからソートする。キーの計算が重いときに使う
+// キーが等しい要素の順序は保たれる
+pub fn sort_by_cached_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+where
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    // key のパニックも比較関数のパニックと同じように扱う
+    let mut keys = vec![];
+    catch_comparator_panic(|| {
+        keys = x.iter().enumerate().map(|(i, v)| (key(v), i)).collect();
+    })?;
+    sort_by(&mut keys, &|a, b| a.cmp(b))?;
+    let mut order: Vec<usize> = keys.into_iter().map(|(_, i)| i).collect();
+    apply_permutation(x, &mut order);
+    Ok(())
+}
+
+// 等しい要素の順序を保つソート
+// バイトニックソートは安定ではないので、添字を並べ替え、比較が等しいときは元の位置で比べる
+pub fn stable_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let mut order: Vec<usize> = (0..x.len()).collect();
+    sort_by(&mut order, &|&i, &j| {
+        comparator(&x[i], &x[j]).then(i.cmp(&j))
+    })?;
+    apply_permutation(x, &mut order);
+    Ok(())
 }
 
 #[cfg(test)]
@@ -173,6 +209,77 @@ mod tests {
             assert!(is_sorted_descending(&x));
         }
     }
+
+    #[test]
+    fn sort_students_by_key() {
+        let ash = Student::new("Ash", "Ketchum", 10);
+        let misty = Student::new("Misty", "Waterflower", 11);
+        let brock = Student::new("Brock", "Takeshi", 15);
+        let pikachu = Student::new("Pikachu", "Pokemon", 3);
+
+        let mut x = vec![&ash, &misty, &brock, &pikachu];
+        assert_eq!(sort_by_key(&mut x, &|s| s.age), Ok(()));
+        assert_eq!(x, vec![&pikachu, &ash, &misty, &brock]);
+
+        let mut x = vec![&ash, &misty, &brock, &pikachu];
+        assert_eq!(sort_by_key(&mut x, &|s| &s.last_name), Ok(()));
+        assert_eq!(x, vec![&ash, &pikachu, &brock, &misty]);
+    }
+
+    #[test]
+    fn sort_students_by_cached_key() {
+        let ash = Student::new("Ash", "Ketchum", 10);
+        let misty = Student::new("Misty", "Waterflower", 11);
+        let brock = Student::new("Brock", "Takeshi", 15);
+        let pikachu = Student::new("Pikachu", "Pokemon", 3);
+
+        // キーは要素ごとに1回だけ計算される
+        let calls = std::cell::Cell::new(0);
+        let mut x = vec![&ash, &misty, &brock, &pikachu];
+        assert_eq!(
+            sort_by_cached_key(&mut x, &|s| {
+                calls.set(calls.get() + 1);
+                s.first_name.to_lowercase()
+            }),
+            Ok(())
+        );
+        assert_eq!(x, vec![&ash, &brock, &misty, &pikachu]);
+        assert_eq!(calls.get(), 4);
+    }
+
+    // 年齢が同じ学生は元の順序のまま
+    #[test]
+    fn stable_sort_students_by_age() {
+        let ash = Student::new("Ash", "Ketchum", 10);
+        let misty = Student::new("Misty", "Waterflower", 10);
+        let brock = Student::new("Brock", "Takeshi", 15);
+        let pikachu = Student::new("Pikachu", "Pokemon", 10);
+
+        let mut x = vec![&brock, &misty, &ash, &pikachu];
+        assert_eq!(stable_sort_by(&mut x, &|a, b| a.age.cmp(&b.age)), Ok(()));
+        assert_eq!(x, vec![&misty, &ash, &pikachu, &brock]);
+    }
+
+    #[test]
+    fn stable_sort_every_length() {
+        for n in 0..=300 {
+            // 上位の桁だけで比べ、下位の桁で元の順序を確かめる
+            let x: Vec<u32> = refined_new_u32_vec(n).iter().map(|v| v % 1000).collect();
+            let mut expected = x.clone();
+            expected.sort_by_key(|v| v / 100);
+
+            let mut y = x.clone();
+            assert_eq!(
+                stable_sort_by(&mut y, &|a, b| (a / 100).cmp(&(b / 100))),
+                Ok(())
+            );
+            assert_eq!(y, expected, "len: {}", n);
+
+            let mut y = x.clone();
+            assert_eq!(sort_by_cached_key(&mut y, &|v| v / 100), Ok(()));
+            assert_eq!(y, expected, "len: {}", n);
+        }
+    }
 }
 
 // クロージャは実装によって異なる型トレイトを自動的に実装する
@@ -0,0 +1,275 @@
+use std::fmt;
+
+use crate::parser::{Ast, AstKind, BinOpKind, UniOpKind};
+use crate::{Annot, Loc};
+
+// x86-64 のアセンブリ（AT&T 記法）に変換するバックエンド
+// 整数の四則演算だけを扱い、式の値は %rax に求める（スタックマシン方式）
+// 生成するのは Linux (ELF) 向けで、printf で結果を表示する main 関数を持つ
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CodegenErrorKind {
+    Unsupported(&'static str), // 整数以外の値や関数呼び出しなど、変換できない式
+    Overflow,                  // i64 に収まらない整数リテラル
+}
+
+pub type CodegenError = Annot<CodegenErrorKind>;
+
+impl fmt::Display for CodegenErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::CodegenErrorKind::*;
+        match self {
+            Unsupported(what) => write!(f, "{} cannot be compiled", what),
+            Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.loc, self.value)
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+// 式を順に評価して1行ずつ表示するプログラムを作る
+// 実行時に 0 で割ったり桁溢れしたりしたら、標準エラーにメッセージを出して終了コード 1 で終わる
+pub fn compile(exprs: &[Ast]) -> Result<String, CodegenError> {
+    let mut g = Codegen::default();
+    g.emit("    .text");
+    g.emit("    .globl main");
+    g.emit("main:");
+    g.emit("    pushq %rbp");
+    g.emit("    movq %rsp, %rbp");
+    for e in exprs {
+        g.compile_expr(e)?;
+        g.emit("    movq %rax, %rsi");
+        g.emit("    leaq .Lfmt(%rip), %rdi");
+        g.emit("    xorl %eax, %eax");
+        g.emit("    call printf@PLT");
+    }
+    g.emit("    xorl %eax, %eax");
+    g.emit("    popq %rbp");
+    g.emit("    ret");
+    // 実行時エラー。%rdi にメッセージを入れて .Lfail に飛ぶ
+    g.emit(".Loverflow:");
+    g.emit("    leaq .Lmsg_overflow(%rip), %rdi");
+    g.emit("    jmp .Lfail");
+    g.emit(".Ldiv_zero:");
+    g.emit("    leaq .Lmsg_div_zero(%rip), %rdi");
+    g.emit(".Lfail:");
+    // 途中まで積んだスタックを捨てて、呼び出し時の 16 バイト境界に戻す
+    g.emit("    movq %rbp, %rsp");
+    g.emit("    movq stderr@GOTPCREL(%rip), %rax");
+    g.emit("    movq (%rax), %rsi");
+    g.emit("    call fputs@PLT");
+    g.emit("    movl $1, %eax");
+    g.emit("    popq %rbp");
+    g.emit("    ret");
+    g.emit("    .section .rodata");
+    g.emit(".Lfmt:");
+    g.emit("    .string \"%ld\\n\"");
+    g.emit(".Lmsg_overflow:");
+    g.emit("    .string \"arithmetic overflow\\n\"");
+    g.emit(".Lmsg_div_zero:");
+    g.emit("    .string \"division by zero\\n\"");
+    // 実行可能スタックを要求しないことをリンカに伝える
+    g.emit("    .section .note.GNU-stack,\"\",@progbits");
+    Ok(g.out)
+}
+
+pub(crate) fn unsupported(what: &'static str, loc: &Loc) -> CodegenError {
+    CodegenError::new(CodegenErrorKind::Unsupported(what), loc.clone())
+}
+
+#[derive(Default)]
+struct Codegen {
+    out: String,
+    // 作ったラベルの数（.L0, .L1, ... と名前をつける）
+    labels: usize,
+}
+
+impl Codegen {
+    fn emit(&mut self, line: &str) {
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn new_label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels - 1)
+    }
+
+    // 式の値を %rax に求めるコードを出力する
+    fn compile_expr(&mut self, expr: &Ast) -> Result<(), CodegenError> {
+        use self::AstKind::*;
+        match &expr.value {
+            Num(n) => {
+                let n = i64::try_from(*n)
+                    .map_err(|_| CodegenError::new(CodegenErrorKind::Overflow, expr.loc.clone()))?;
+                // 32 ビットに収まらない即値は movabsq で読み込む
+                if i32::try_from(n).is_ok() {
+                    self.emit(&format!("    movq ${}, %rax", n));
+                } else {
+                    self.emit(&format!("    movabsq ${}, %rax", n));
+                }
+            }
+            UniOp { op, e } => {
+                self.compile_expr(e)?;
+                match op.value {
+                    UniOpKind::Plus => {}
+                    UniOpKind::Minus => {
+                        self.emit("    negq %rax");
+                        self.emit("    jo .Loverflow");
+                    }
+                    UniOpKind::Not => self.emit("    notq %rax"),
+                }
+            }
+            BinOp { op, l, r } => {
+                // 右辺を先に求めて積んでおき、左辺を %rax、右辺を %rcx にそろえる
+                self.compile_expr(r)?;
+                self.emit("    pushq %rax");
+                self.compile_expr(l)?;
+                self.emit("    popq %rcx");
+                match op.value {
+                    BinOpKind::Add => self.emit("    addq %rcx, %rax"),
+                    BinOpKind::Sub => self.emit("    subq %rcx, %rax"),
+                    BinOpKind::Mult => self.emit("    imulq %rcx, %rax"),
+                    BinOpKind::Div => {
+                        self.emit("    testq %rcx, %rcx");
+                        self.emit("    jz .Ldiv_zero");
+                        // i64::MIN / -1 は idiv が例外を起こすので、-1 で割るときは先に符号反転で調べる
+                        let label = self.new_label();
+                        self.emit("    cmpq $-1, %rcx");
+                        self.emit(&format!("    jne {}", label));
+                        self.emit("    movq %rax, %rdx");
+                        self.emit("    negq %rdx");
+                        self.emit("    jo .Loverflow");
+                        self.emit(&format!("{}:", label));
+                        // %rdx:%rax を %rcx で割る。cqto で %rax の符号を %rdx に広げる
+                        self.emit("    cqto");
+                        self.emit("    idivq %rcx");
+                    }
+                    BinOpKind::BitAnd => self.emit("    andq %rcx, %rax"),
+                    BinOpKind::BitOr => self.emit("    orq %rcx, %rax"),
+                    BinOpKind::BitXor => self.emit("    xorq %rcx, %rax"),
+                    // シフト量が 0..64 の外ならインタプリタと同じく桁溢れにする
+                    BinOpKind::Shl | BinOpKind::Shr => {
+                        self.emit("    cmpq $63, %rcx");
+                        self.emit("    ja .Loverflow");
+                        if op.value == BinOpKind::Shl {
+                            self.emit("    shlq %cl, %rax");
+                        } else {
+                            self.emit("    sarq %cl, %rax");
+                        }
+                    }
+                }
+                if matches!(op.value, BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mult) {
+                    self.emit("    jo .Loverflow");
+                }
+            }
+            Float(_) => return Err(unsupported("float", &expr.loc)),
+            Imaginary(_) => return Err(unsupported("complex number", &expr.loc)),
+            Str(_) => return Err(unsupported("string", &expr.loc)),
+            Matrix(_) => return Err(unsupported("matrix", &expr.loc)),
+            Var(_) => return Err(unsupported("variable", &expr.loc)),
+            WithUnit { .. } | Convert { .. } => return Err(unsupported("unit", &expr.loc)),
+            Call { .. } => return Err(unsupported("function call", &expr.loc)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex, parse, Interpreter};
+
+    fn ast(src: &str) -> Ast {
+        parse(lex(src).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_compile() {
+        let out = compile(&[ast("-(1 + 2)")]).unwrap();
+        let body: Vec<&str> = out.lines().skip(5).take(9).collect();
+        assert_eq!(
+            body,
+            vec![
+                "    movq $2, %rax",
+                "    pushq %rax",
+                "    movq $1, %rax",
+                "    popq %rcx",
+                "    addq %rcx, %rax",
+                "    jo .Loverflow",
+                "    negq %rax",
+                "    jo .Loverflow",
+                "    movq %rax, %rsi",
+            ]
+        );
+        assert_eq!(
+            compile(&[ast("1 + 2.5")]),
+            Err(unsupported("float", &Loc(4, 7)))
+        );
+        assert!(compile(&[ast("4294967296")])
+            .unwrap()
+            .contains("movabsq $4294967296, %rax"));
+    }
+
+    // cc があれば実際にアセンブル・実行して、インタプリタと同じ結果になるか確かめる
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn test_native() {
+        use std::process::Command;
+
+        if Command::new("cc").arg("--version").output().is_err() {
+            eprintln!("cc is not available; skipping");
+            return;
+        }
+
+        let cases = [
+            "1 + 2 * 3",
+            "(1 + 2) * 3",
+            "-7 / 2",
+            "7 / -2",
+            "-(4 - 10) * 3 - -2",
+            "9223372036854775807",
+            "100000 * 100000 * 100",
+            "1 / 0",
+            "9223372036854775807 + 1",
+            "(0 - 9223372036854775807 - 1) / -1",
+            "~5 & 0xff | 1 << 62 ^ -64 >> 3",
+            "1 << 64",
+        ];
+        let dir = std::env::temp_dir().join(format!("ch9-asm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (i, src) in cases.iter().enumerate() {
+            let ast = ast(src);
+            let s = dir.join(format!("case{}.s", i));
+            let exe = dir.join(format!("case{}", i));
+            std::fs::write(&s, compile(std::slice::from_ref(&ast)).unwrap()).unwrap();
+            let status = Command::new("cc")
+                .arg("-o")
+                .arg(&exe)
+                .arg(&s)
+                .status()
+                .unwrap();
+            assert!(status.success(), "cc failed for {:?}", src);
+
+            let output = Command::new(&exe).output().unwrap();
+            match Interpreter::new().eval(&ast) {
+                Ok(v) => {
+                    assert_eq!(output.status.code(), Some(0), "{:?}", src);
+                    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}\n", v));
+                }
+                // インタプリタがエラーにする式は、実行時エラーで終了する
+                Err(_) => {
+                    assert_eq!(output.status.code(), Some(1), "{:?}", src);
+                    assert!(output.stdout.is_empty());
+                }
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,9 @@
+// エディタから起動される LSP サーバー。標準入出力で JSON-RPC をやり取りする
+fn main() {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    if let Err(e) = ch9::lsp::run(stdin.lock(), stdout.lock()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+
+use crate::interpreter::{operands, Interpreter, InterpreterError, Value};
+use crate::parser::{Ast, AstKind, BinOpKind, UniOpKind};
+use crate::Loc;
+
+// 式を1段ずつ簡約しながら評価する（REPL の :explain）
+// (1 + 2) * 3 → 3 * 3 → 9 のように、各段階の式と次に簡約する部分式の位置を記録する
+
+// 簡約の途中の式。値が求まった部分式は Done になる
+enum Term<'a> {
+    Done(Value),
+    Pending { ast: &'a Ast, args: Vec<Term<'a>> },
+}
+
+impl<'a> Term<'a> {
+    fn new(ast: &'a Ast) -> Self {
+        Term::Pending {
+            ast,
+            args: operands(ast).into_iter().map(Term::new).collect(),
+        }
+    }
+
+    // 左から見て最初に簡約できる（部分式の値がすべて求まった）部分式への道順
+    fn next_redex(&self, path: &mut Vec<usize>) -> bool {
+        match self {
+            Term::Done(_) => false,
+            Term::Pending { args, .. } => {
+                for (i, arg) in args.iter().enumerate() {
+                    path.push(i);
+                    if arg.next_redex(path) {
+                        return true;
+                    }
+                    path.pop();
+                }
+                true
+            }
+        }
+    }
+
+    fn get_mut(&mut self, path: &[usize]) -> &mut Term<'a> {
+        match (path.split_first(), self) {
+            (None, term) => term,
+            (Some((&i, rest)), Term::Pending { args, .. }) => args[i].get_mut(rest),
+            (Some(_), Term::Done(_)) => unreachable!(),
+        }
+    }
+}
+
+// 簡約の1段。text は簡約する前の式、focus は text の中で次に簡約する部分式の範囲
+// loc は同じ部分式の入力の中での位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub text: String,
+    pub focus: Loc,
+    pub loc: Loc,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub steps: Vec<Step>,
+    pub result: Result<Value, InterpreterError>,
+}
+
+impl Explanation {
+    // (1 + 2) * 3 → 3 * 3 → 9 の形。エラーで止まったときは最後の式まで
+    pub fn summary(&self) -> String {
+        let mut parts: Vec<String> = self.steps.iter().map(|s| s.text.clone()).collect();
+        if let Ok(v) = &self.result {
+            parts.push(render_value(v));
+        }
+        parts.join(" → ")
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step.text)?;
+            writeln!(
+                f,
+                "{}{}",
+                " ".repeat(step.focus.0),
+                "^".repeat(step.focus.1 - step.focus.0)
+            )?;
+        }
+        // エラーは位置つきで表示したいので、呼び出し側に任せる
+        match &self.result {
+            Ok(v) => writeln!(f, "= {}", render_value(v)),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+impl Interpreter {
+    pub fn explain(&mut self, expr: &Ast) -> Explanation {
+        let mut term = Term::new(expr);
+        let mut steps = vec![];
+        let mut path = vec![];
+        while term.next_redex(&mut path) {
+            let (text, focus) = render(&term, &path);
+            let node = term.get_mut(&path);
+            let (ast, args) = match std::mem::replace(node, Term::Done(Value::Int(0))) {
+                Term::Pending { ast, args } => (ast, args),
+                Term::Done(_) => unreachable!(),
+            };
+            let args = args
+                .into_iter()
+                .map(|arg| match arg {
+                    Term::Done(v) => v,
+                    Term::Pending { .. } => unreachable!(),
+                })
+                .collect();
+            let step = Step {
+                text,
+                focus,
+                loc: ast.loc.clone(),
+            };
+            match self.reduce(ast, args) {
+                Ok(v) => *node = Term::Done(v),
+                Err(e) => {
+                    steps.push(step);
+                    return Explanation {
+                        steps,
+                        result: Err(e),
+                    };
+                }
+            }
+            // リテラルのように見た目の変わらない簡約は記録しない
+            // 2i → (2i) のように括弧がつくだけのものも同じ
+            if strip_parens(&render(&term, &[]).0) != strip_parens(&step.text) {
+                steps.push(step);
+            }
+            path.clear();
+        }
+        let result = match term {
+            Term::Done(v) => Ok(v),
+            Term::Pending { .. } => unreachable!(),
+        };
+        Explanation { steps, result }
+    }
+}
+
+// 式を文字列にする。focus の道順にある部分式の範囲も返す
+fn render(term: &Term, focus: &[usize]) -> (String, Loc) {
+    let mut printer = Printer {
+        out: String::new(),
+        focus: Loc(0, 0),
+    };
+    printer.term(term, Some(focus));
+    (printer.out, printer.focus)
+}
+
+fn strip_parens(text: &str) -> String {
+    text.chars().filter(|&c| c != '(' && c != ')').collect()
+}
+
+fn render_value(v: &Value) -> String {
+    match v {
+        Value::Str(s) => format!("{:?}", s),
+        v => v.to_string(),
+    }
+}
+
+// 結合の強さ。数が大きいほど強い
+const PREC_CONVERT: u8 = 0;
+const PREC_UNARY: u8 = 7;
+const PREC_ATOM: u8 = 8;
+
+fn binop_prec(op: &BinOpKind) -> u8 {
+    use self::BinOpKind::*;
+    match op {
+        BitOr => 1,
+        BitXor => 2,
+        BitAnd => 3,
+        Shl | Shr => 4,
+        Add | Sub => 5,
+        Mult | Div => 6,
+    }
+}
+
+fn prec(term: &Term) -> u8 {
+    match term {
+        // 3 m は単項演算と同じ強さで結合する。1+2i のように + や - を含む複素数は括弧で囲む
+        Term::Done(Value::Quantity(_)) => PREC_UNARY,
+        Term::Done(v) => {
+            let text = render_value(v);
+            if matches!(v, Value::Complex(_)) && text[1..].contains(['+', '-']) {
+                PREC_CONVERT
+            } else if text.starts_with('-') {
+                // 負の数は単項の - と同じ強さ
+                PREC_UNARY
+            } else {
+                PREC_ATOM
+            }
+        }
+        Term::Pending { ast, .. } => match &ast.value {
+            AstKind::Convert { .. } => PREC_CONVERT,
+            AstKind::UniOp { .. } => PREC_UNARY,
+            AstKind::BinOp { op, .. } => binop_prec(&op.value),
+            _ => PREC_ATOM,
+        },
+    }
+}
+
+struct Printer {
+    out: String,
+    focus: Loc,
+}
+
+impl Printer {
+    // focus が Some(&[]) ならこの部分式が次に簡約するもの
+    fn term(&mut self, term: &Term, focus: Option<&[usize]>) {
+        let start = self.out.len();
+        match term {
+            Term::Done(v) => self.out.push_str(&render_value(v)),
+            Term::Pending { ast, args } => self.pending(ast, args, focus),
+        }
+        if focus == Some(&[]) {
+            self.focus = Loc(start, self.out.len());
+        }
+    }
+
+    // i 番目の部分式。min より弱く結合するなら括弧で囲む
+    fn arg(&mut self, args: &[Term], i: usize, focus: Option<&[usize]>, min: u8) {
+        let focus = match focus {
+            Some([j, rest @ ..]) if *j == i => Some(rest),
+            _ => None,
+        };
+        let paren = prec(&args[i]) < min;
+        if paren {
+            self.out.push('(');
+        }
+        self.term(&args[i], focus);
+        if paren {
+            self.out.push(')');
+        }
+    }
+
+    fn pending(&mut self, ast: &Ast, args: &[Term], focus: Option<&[usize]>) {
+        use self::AstKind::*;
+        match &ast.value {
+            Num(n) => self.out.push_str(&n.to_string()),
+            Float(x) => self.out.push_str(&x.to_string()),
+            Imaginary(x) => self.out.push_str(&format!("{}i", x)),
+            Str(s) => self.out.push_str(&format!("{:?}", s)),
+            Var(name) => self.out.push_str(name),
+            Matrix(rows) => {
+                self.out.push('[');
+                let mut i = 0;
+                for (r, row) in rows.iter().enumerate() {
+                    if r > 0 {
+                        self.out.push_str("; ");
+                    }
+                    for c in 0..row.len() {
+                        if c > 0 {
+                            self.out.push_str(", ");
+                        }
+                        self.arg(args, i, focus, PREC_CONVERT);
+                        i += 1;
+                    }
+                }
+                self.out.push(']');
+            }
+            WithUnit { unit, .. } => {
+                self.arg(args, 0, focus, PREC_ATOM);
+                self.out.push(' ');
+                self.unit(unit);
+            }
+            Convert { unit, .. } => {
+                self.arg(args, 0, focus, 1);
+                self.out.push_str(" in ");
+                // 単位は評価しないので、そのまま書く
+                self.term(&Term::new(unit), None);
+            }
+            Call { name, .. } => {
+                self.out.push_str(name);
+                self.out.push('(');
+                for i in 0..args.len() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.arg(args, i, focus, PREC_CONVERT);
+                }
+                self.out.push(')');
+            }
+            UniOp { op, .. } => {
+                self.out.push(match op.value {
+                    UniOpKind::Plus => '+',
+                    UniOpKind::Minus => '-',
+                    UniOpKind::Not => '~',
+                });
+                // 求まった値は括弧で囲み、--3 ではなく -(-3) と書く
+                let min = match args[0] {
+                    Term::Done(_) => PREC_ATOM,
+                    Term::Pending { .. } => PREC_UNARY,
+                };
+                self.arg(args, 0, focus, min);
+            }
+            BinOp { op, .. } => {
+                use self::BinOpKind::*;
+                let p = binop_prec(&op.value);
+                self.arg(args, 0, focus, p);
+                self.out.push_str(match op.value {
+                    Add => " + ",
+                    Sub => " - ",
+                    Mult => " * ",
+                    Div => " / ",
+                    BitAnd => " & ",
+                    BitOr => " | ",
+                    BitXor => " ^ ",
+                    Shl => " << ",
+                    Shr => " >> ",
+                });
+                self.arg(args, 1, focus, p + 1);
+            }
+        }
+    }
+
+    // 数値につく単位は空白を挟まずに書く（km/h）
+    fn unit(&mut self, ast: &Ast) {
+        match &ast.value {
+            AstKind::BinOp { op, l, r } => {
+                self.unit(l);
+                self.out.push(if op.value == BinOpKind::Mult {
+                    '*'
+                } else {
+                    '/'
+                });
+                self.unit(r);
+            }
+            AstKind::Var(name) => self.out.push_str(name),
+            _ => self.term(&Term::new(ast), None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::InterpreterErrorKind;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    fn explain(input: &str) -> Explanation {
+        let ast = parse(lex(input).unwrap()).unwrap();
+        let mut interp = Interpreter::new();
+        interp.set_var("x", 10).unwrap();
+        interp.explain(&ast)
+    }
+
+    #[test]
+    fn test_explain() {
+        let e = explain("(1 + 2) * 3");
+        assert_eq!(e.summary(), "(1 + 2) * 3 → 3 * 3 → 9");
+        assert_eq!(e.steps[0].focus, Loc(1, 6));
+        assert_eq!(e.steps[0].loc, Loc(1, 6));
+        assert_eq!(e.steps[1].focus, Loc(0, 5));
+        assert_eq!(e.to_string(), "(1 + 2) * 3\n ^^^^^\n3 * 3\n^^^^^\n= 9\n");
+
+        assert_eq!(
+            explain("x - (2 - 3)").summary(),
+            "x - (2 - 3) → 10 - (2 - 3) → 10 - -1 → 11"
+        );
+        assert_eq!(
+            explain("-(1 + 2) * x").summary(),
+            "-(1 + 2) * x → -3 * x → -3 * 10 → -30"
+        );
+        assert_eq!(
+            explain("len(upper(\"ab\") + \"c\")").summary(),
+            "len(upper(\"ab\") + \"c\") → len(\"AB\" + \"c\") → len(\"ABc\") → 3"
+        );
+        assert_eq!(
+            explain("1 km + 500 m in m").summary(),
+            "1 km + 500 m in m → 1.5 km in m → 1500 m"
+        );
+        assert_eq!(explain("42").summary(), "42");
+        assert_eq!(explain("-(0 - 3)").summary(), "-(0 - 3) → -(-3) → 3");
+        assert_eq!(
+            explain("2 - -(1 - 4)").summary(),
+            "2 - -(1 - 4) → 2 - -(-3) → 2 - 3 → -1"
+        );
+        assert_eq!(
+            explain("1 + 2i * 3").summary(),
+            "1 + 2i * 3 → 1 + 6i → 1+6i"
+        );
+
+        // エラーが起きたら、その部分式を指したまま止まる
+        let e = explain("1 + 6 / (2 - 2)");
+        assert_eq!(e.summary(), "1 + 6 / (2 - 2) → 1 + 6 / 0");
+        assert_eq!(e.steps[1].focus, Loc(4, 9));
+        assert_eq!(
+            e.result,
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DivisionByZero,
+                Loc(4, 14)
+            ))
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use crate::int::Radix;
+use crate::interpreter::Value;
+
+// 評価結果を表示するときの書式。REPL の :fmt, :prec, :sep で切り替える
+
+// 実数の表記
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Notation {
+    #[default]
+    Plain, // 12345.678
+    Scientific,  // 1.2345678e4
+    Engineering, // 12.345678e3（指数は 3 の倍数）
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Formatter {
+    pub radix: Radix,             // 整数の基数
+    pub notation: Notation,       // 実数の表記
+    pub precision: Option<usize>, // 小数点以下の桁数（{:.4} と同じ）。None なら必要なだけ
+    pub separators: bool,         // 10 進は 3 桁ごとに ,、それ以外は 4 桁ごとに _ で区切る
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Formatter::default()
+    }
+
+    // dec, hex, oct, bin は整数の基数、sci, eng は実数の表記を切り替える
+    // dec を指定すると基数も表記も最初の状態に戻る。知らない名前なら false
+    pub fn set_mode(&mut self, name: &str) -> bool {
+        match name {
+            "dec" => {
+                self.radix = Radix::Dec;
+                self.notation = Notation::Plain;
+            }
+            "sci" => self.notation = Notation::Scientific,
+            "eng" => self.notation = Notation::Engineering,
+            _ => match Radix::from_name(name) {
+                Some(radix) => self.radix = radix,
+                None => return false,
+            },
+        }
+        true
+    }
+
+    pub fn format(&self, v: &Value) -> String {
+        match v {
+            Value::Int(_) | Value::Fixed(_) => self.format_int(v),
+            Value::Float(x) => self.format_f64(*x),
+            Value::Quantity(q) => match &q.unit {
+                Some(unit) => format!("{} {}", self.format_f64(q.value / unit.factor), unit.name),
+                None => format!("{} {}", self.format_f64(q.value), q.dim),
+            },
+            Value::Complex(c) => {
+                if c.re == 0.0 {
+                    format!("{}i", self.format_f64(c.im))
+                } else if c.im < 0.0 {
+                    format!("{}-{}i", self.format_f64(c.re), self.format_f64(-c.im))
+                } else {
+                    format!("{}+{}i", self.format_f64(c.re), self.format_f64(c.im))
+                }
+            }
+            Value::Matrix(m) => {
+                let rows: Vec<String> = m
+                    .iter_rows()
+                    .map(|row| {
+                        row.iter()
+                            .map(|&x| self.format_f64(x))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .collect();
+                format!("[{}]", rows.join("; "))
+            }
+            Value::Str(s) => s.clone(),
+        }
+    }
+
+    // 整数は 10 進の普通の表記なら桁を落とさずに表示する。指数表記では実数として扱う
+    fn format_int(&self, v: &Value) -> String {
+        match (self.radix, self.notation) {
+            (Radix::Dec, Notation::Plain) => {
+                let s = v.to_string();
+                if self.separators {
+                    group_thousands(&s)
+                } else {
+                    s
+                }
+            }
+            (Radix::Dec, _) => self.format_f64(v.as_f64()),
+            (radix, _) => {
+                let s = v.to_string_radix(radix);
+                if self.separators {
+                    // 0x などの接頭辞の後ろを区切る
+                    let (prefix, digits) = s.split_at(2);
+                    format!("{}{}", prefix, group_digits(digits, 4, '_'))
+                } else {
+                    s
+                }
+            }
+        }
+    }
+
+    pub fn format_f64(&self, x: f64) -> String {
+        if !x.is_finite() {
+            return x.to_string();
+        }
+        let s = match self.notation {
+            Notation::Plain => self.fixed(x),
+            Notation::Scientific => match self.precision {
+                Some(p) => format!("{:.*e}", p, x),
+                None => format!("{:e}", x),
+            },
+            Notation::Engineering => self.engineering(x),
+        };
+        if self.separators {
+            group_thousands(&s)
+        } else {
+            s
+        }
+    }
+
+    fn fixed(&self, x: f64) -> String {
+        match self.precision {
+            Some(p) => format!("{:.*}", p, x),
+            None => x.to_string(),
+        }
+    }
+
+    // 仮数が 1 以上 1000 未満になるように、指数を 3 の倍数にそろえる
+    fn engineering(&self, x: f64) -> String {
+        if x == 0.0 {
+            return format!("{}e0", self.fixed(x));
+        }
+        let mut exp = (x.abs().log10().floor() as i32).div_euclid(3) * 3;
+        let mut mantissa = self.fixed(x / 10f64.powi(exp));
+        // 丸めで 1000 になったら次の指数にする（999.99995 を小数点以下 3 桁にしたときなど）
+        if mantissa.trim_start_matches('-').starts_with("1000") {
+            exp += 3;
+            mantissa = self.fixed(x / 10f64.powi(exp));
+        }
+        format!("{}e{}", mantissa, exp)
+    }
+}
+
+// 10 進の数字列の整数部を 3 桁ごとに , で区切る（-1234567.5 → -1,234,567.5）
+fn group_thousands(s: &str) -> String {
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", s),
+    };
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    format!(
+        "{}{}{}",
+        sign,
+        group_digits(&rest[..end], 3, ','),
+        &rest[end..]
+    )
+}
+
+// 数字列を下の桁から n 桁ごとに sep で区切る
+fn group_digits(digits: &str, n: usize, sep: char) -> String {
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(n) {
+            out.push(sep);
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Engine;
+
+    #[test]
+    fn test_formatter() {
+        let mut engine = Engine::new();
+        let mut f = Formatter::new();
+        let mut show = |f: &Formatter, input: &str| f.format(&engine.eval(input).unwrap());
+
+        assert_eq!(show(&f, "1234567"), "1234567");
+        assert_eq!(show(&f, "-12345.678"), "-12345.678");
+        f.separators = true;
+        assert_eq!(show(&f, "1234567"), "1,234,567");
+        assert_eq!(show(&f, "-12345.678"), "-12,345.678");
+        assert_eq!(show(&f, "123"), "123");
+
+        f.precision = Some(4);
+        assert_eq!(show(&f, "26.284 * 1000"), "26,284.0000");
+        f.separators = false;
+        assert_eq!(show(&f, "1 / 3.0 + 26"), "26.3333");
+        assert_eq!(show(&f, "2 km + 1 m"), "2.0010 km");
+        assert_eq!(show(&f, "(1 + 2i) / 3"), "0.3333+0.6667i");
+        assert_eq!(show(&f, "[1, 2] / 3"), "[0.3333, 0.6667]");
+
+        assert!(f.set_mode("sci"));
+        assert_eq!(show(&f, "123456.0"), "1.2346e5");
+        assert_eq!(show(&f, "123456"), "1.2346e5");
+        assert!(f.set_mode("eng"));
+        assert_eq!(show(&f, "123456.0"), "123.4560e3");
+        assert_eq!(show(&f, "-0.00012"), "-120.0000e-6");
+        assert_eq!(show(&f, "999.99999 * 1000"), "1.0000e6");
+        assert_eq!(show(&f, "0.0"), "0.0000e0");
+        f.precision = None;
+        assert_eq!(show(&f, "1500.0"), "1.5e3");
+
+        assert!(f.set_mode("dec"));
+        assert!(f.set_mode("hex"));
+        assert_eq!(show(&f, "255"), "0xff");
+        assert_eq!(show(&f, "2.5"), "2.5");
+        f.separators = true;
+        assert_eq!(show(&f, "0xdeadbeef"), "0xdead_beef");
+        assert!(f.set_mode("bin"));
+        assert_eq!(show(&f, "0b101010"), "0b10_1010");
+        assert!(f.set_mode("oct"));
+        assert_eq!(show(&f, "8"), "0o10");
+        assert!(!f.set_mode("roman"));
+    }
+}
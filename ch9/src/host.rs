@@ -0,0 +1,115 @@
+use crate::interpreter::{InterpreterErrorKind, Value};
+
+// 登録できる Rust の関数（クロージャ）を表すトレイト
+// ch2 rpn の apply2 が F: Fn(f64, f64) -> f64 を受け取るのと同じように、
+// クロージャの型ごとに impl を用意して引数の数の違いを吸収する
+// Args は impl を区別するためだけの型パラメータ（(f64,) なら1引数、(f64, f64) なら2引数）
+pub trait HostFn<Args> {
+    fn call(&self, args: &[Value]) -> Result<Value, InterpreterErrorKind>;
+}
+
+// 引数の数を問わず、Value のスライスをそのまま受け取る関数の印
+pub struct Variadic;
+
+// f64 を受け取る関数には実数しか渡せない
+// 次元を持つ量は、単位を黙って落とさないように渡さない
+fn real(v: &Value) -> Result<f64, InterpreterErrorKind> {
+    let x = match v {
+        Value::Quantity(q) if !q.dim.is_none() => None,
+        v => v.to_f64(),
+    };
+    x.ok_or(InterpreterErrorKind::TypeMismatch {
+        expected: "real",
+        found: v.type_name(),
+    })
+}
+
+fn check_arity(args: &[Value], expected: usize) -> Result<(), InterpreterErrorKind> {
+    if args.len() == expected {
+        Ok(())
+    } else {
+        Err(InterpreterErrorKind::ArityMismatch {
+            expected,
+            found: args.len(),
+        })
+    }
+}
+
+impl<F> HostFn<(f64,)> for F
+where
+    F: Fn(f64) -> f64,
+{
+    fn call(&self, args: &[Value]) -> Result<Value, InterpreterErrorKind> {
+        check_arity(args, 1)?;
+        Ok(Value::Float(self(real(&args[0])?)))
+    }
+}
+
+impl<F> HostFn<(f64, f64)> for F
+where
+    F: Fn(f64, f64) -> f64,
+{
+    fn call(&self, args: &[Value]) -> Result<Value, InterpreterErrorKind> {
+        check_arity(args, 2)?;
+        Ok(Value::Float(self(real(&args[0])?, real(&args[1])?)))
+    }
+}
+
+impl<F> HostFn<Variadic> for F
+where
+    F: Fn(&[Value]) -> Result<Value, String>,
+{
+    fn call(&self, args: &[Value]) -> Result<Value, InterpreterErrorKind> {
+        self(args).map_err(InterpreterErrorKind::HostError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_register_closures() {
+        let mut engine = Engine::new();
+        let scale = 10.0;
+        engine.register_fn("sqrt", f64::sqrt);
+        engine.register_fn("scale", move |x: f64| x * scale);
+        engine.register_fn("pow", |x: f64, y: f64| x.powf(y));
+        engine.register_fn("sum", |args: &[Value]| {
+            Ok(Value::Float(args.iter().map(Value::as_f64).sum()))
+        });
+        engine.register_fn("fail", |_: &[Value]| Err("host failure".to_string()));
+
+        assert_eq!(engine.eval("sqrt(16)"), Ok(Value::Float(4.0)));
+        assert_eq!(engine.eval("scale(2) + 1"), Ok(Value::Float(21.0)));
+        assert_eq!(engine.eval("pow(2, 10)"), Ok(Value::Float(1024.0)));
+        assert_eq!(engine.eval("sum(1, 2, 3)"), Ok(Value::Float(6.0)));
+        assert_eq!(
+            engine.eval("1 + pow(2)"),
+            Err(Error::Interpreter(InterpreterError::new(
+                InterpreterErrorKind::ArityMismatch {
+                    expected: 2,
+                    found: 1
+                },
+                Loc(4, 10)
+            )))
+        );
+        assert_eq!(
+            engine.eval("sqrt(4 km)"),
+            Err(Error::Interpreter(InterpreterError::new(
+                InterpreterErrorKind::TypeMismatch {
+                    expected: "real",
+                    found: "quantity"
+                },
+                Loc(0, 10)
+            )))
+        );
+        assert_eq!(
+            engine.eval("fail()"),
+            Err(Error::Interpreter(InterpreterError::new(
+                InterpreterErrorKind::HostError("host failure".to_string()),
+                Loc(0, 6)
+            )))
+        );
+    }
+}
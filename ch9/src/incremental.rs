@@ -0,0 +1,352 @@
+use std::ops::Range;
+
+use crate::lexer::{lex_token, LexError, Token, TokenKind};
+use crate::parser::{parse, Ast, AstKind, ParseError};
+use crate::{Annot, Loc};
+
+// エディタからの編集。元のテキストの range (バイト位置) を text に置き換える
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Loc,
+    pub text: String,
+}
+
+// トークンの長さを決めるときに先読みするバイト数（"2.5" や "2i" の判定で2文字先まで見る）
+const LOOKAHEAD: usize = 2;
+
+impl TextEdit {
+    pub fn new(range: Loc, text: impl Into<String>) -> Self {
+        TextEdit {
+            range,
+            text: text.into(),
+        }
+    }
+
+    // 編集後のテキストを作る
+    pub fn apply(&self, src: &str) -> String {
+        let mut s = String::with_capacity(src.len() + self.text.len());
+        s.push_str(&src[..self.range.0]);
+        s.push_str(&self.text);
+        s.push_str(&src[self.range.1..]);
+        s
+    }
+
+    // 編集後のテキストで、置き換えた部分が終わる位置
+    fn new_end(&self) -> usize {
+        self.range.0 + self.text.len()
+    }
+
+    // 編集より後ろの位置を、編集後のテキストでの位置に直す
+    fn shift(&self, pos: usize) -> usize {
+        pos + self.text.len() - (self.range.1 - self.range.0)
+    }
+
+    // 編集と重ならない位置情報を、編集後のテキストでの位置情報に直す
+    fn shift_loc(&self, loc: &Loc) -> Loc {
+        if loc.1 <= self.range.0 {
+            loc.clone()
+        } else {
+            Loc(self.shift(loc.0), self.shift(loc.1))
+        }
+    }
+
+    fn shift_annot<T: Clone>(&self, a: &Annot<T>) -> Annot<T> {
+        Annot::new(a.value.clone(), self.shift_loc(&a.loc))
+    }
+}
+
+// 再字句解析の結果。tokens[relexed] が新しく切り出したトークンで、それ以外は元のトークンを使い回している
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relexed {
+    pub tokens: Vec<Token>,
+    pub relexed: Range<usize>,
+}
+
+// 編集前のテキストとトークン列から、編集後のトークン列を作る
+// 編集箇所より前のトークンはそのまま使い、編集箇所から字句解析をやり直す。
+// 編集箇所より後ろで元のトークンと同じ位置から切り出し始めたら、残りは元のトークンの位置をずらして使う
+pub fn relex(old_src: &str, old_tokens: &[Token], edit: &TextEdit) -> Result<Relexed, LexError> {
+    let new_src = edit.apply(old_src);
+    let input = new_src.as_bytes();
+
+    // 先読みの範囲まで編集に触れていないトークンは変わらない
+    let keep = old_tokens
+        .iter()
+        .take_while(|tok| tok.loc.1 + LOOKAHEAD <= edit.range.0)
+        .count();
+    let mut tokens = old_tokens[..keep].to_vec();
+    let mut pos = tokens.last().map_or(0, |tok| tok.loc.1);
+
+    // 編集箇所より後ろにある元のトークン
+    let mut rest = old_tokens[keep..]
+        .iter()
+        .skip_while(|tok| tok.loc.0 < edit.range.1)
+        .peekable();
+
+    while let Some((tok, p)) = lex_token(input, pos)? {
+        if tok.loc.0 >= edit.new_end() {
+            while rest
+                .peek()
+                .is_some_and(|old| edit.shift(old.loc.0) < tok.loc.0)
+            {
+                rest.next();
+            }
+            // 同じ位置から字句解析を始めれば、それ以降は同じトークン列になる
+            if rest
+                .peek()
+                .is_some_and(|old| edit.shift(old.loc.0) == tok.loc.0)
+            {
+                let relexed = keep..tokens.len();
+                tokens.extend(
+                    rest.map(|old| Token::new(old.value.clone(), edit.shift_loc(&old.loc))),
+                );
+                return Ok(Relexed { tokens, relexed });
+            }
+        }
+        tokens.push(tok);
+        pos = p;
+    }
+
+    let relexed = keep..tokens.len();
+    Ok(Relexed { tokens, relexed })
+}
+
+// 編集前の Ast とトークン列、編集後のトークン列から、編集後の Ast を作る
+// 編集箇所を含み、編集の前後どちらでも括弧やカンマで区切られている部分式だけを
+// 構文解析し直して元の木に差し込む。そのような部分式が見つからなければ全体を構文解析し直す
+pub fn reparse(
+    old_ast: &Ast,
+    old_tokens: &[Token],
+    tokens: &[Token],
+    edit: &TextEdit,
+) -> Result<Ast, ParseError> {
+    match reparse_region(old_ast, old_tokens, tokens, edit) {
+        Some(ast) => Ok(ast),
+        None => parse(tokens.to_vec()),
+    }
+}
+
+fn reparse_region(
+    old_ast: &Ast,
+    old_tokens: &[Token],
+    tokens: &[Token],
+    edit: &TextEdit,
+) -> Option<Ast> {
+    // 編集箇所を内側に含むノードを根から順に集め、深いものから試す
+    // 端に触れる編集（"3" の直後への挿入など）はノードの範囲そのものを変えるので、外側のノードに任せる
+    let mut path = vec![];
+    let mut node = Some(old_ast);
+    while let Some(n) = node {
+        path.push(n);
+        node = n
+            .children()
+            .into_iter()
+            .find(|c| c.loc.0 < edit.range.0 && edit.range.1 < c.loc.1);
+    }
+
+    // 根を解析し直すのは全体を解析し直すのと同じなので除く
+    path.into_iter().skip(1).rev().find_map(|target| {
+        // 括弧の途中で終わっている部分式（"(2 + 3)" の手前までの "x * (2 + 3" など）は使えない
+        delimited_region(old_tokens, &target.loc)?;
+        let new_loc = Loc(target.loc.0, edit.shift(target.loc.1));
+        let region = delimited_region(tokens, &new_loc)?;
+        let new_node = parse(tokens[region].to_vec()).ok()?;
+        Some(splice(old_ast, target, &new_node, edit))
+    })
+}
+
+fn contains(loc: &Loc, range: &Loc) -> bool {
+    loc.0 <= range.0 && range.1 <= loc.1
+}
+
+// loc の範囲にちょうど収まるトークンの範囲。前後が式の区切り（括弧・カンマ・セミコロンか入力の端）でなければ None
+fn delimited_region(tokens: &[Token], loc: &Loc) -> Option<Range<usize>> {
+    let start = tokens.iter().take_while(|tok| tok.loc.0 < loc.0).count();
+    let end = tokens.iter().take_while(|tok| tok.loc.0 < loc.1).count();
+
+    // 範囲の境界をまたぐトークンがあれば区切られていない
+    if start > 0 && tokens[start - 1].loc.1 > loc.0 {
+        return None;
+    }
+    if end > 0 && tokens[end - 1].loc.1 > loc.1 {
+        return None;
+    }
+
+    let opens = |tok: &Token| {
+        matches!(
+            tok.value,
+            TokenKind::LParen | TokenKind::LBracket | TokenKind::Comma | TokenKind::Semicolon
+        )
+    };
+    let closes = |tok: &Token| {
+        matches!(
+            tok.value,
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::Comma | TokenKind::Semicolon
+        )
+    };
+    // 範囲の中の括弧が閉じていなければ、外側の括弧の対応が変わってしまう
+    let mut depth = 0i32;
+    for tok in &tokens[start..end] {
+        match tok.value {
+            TokenKind::LParen | TokenKind::LBracket => depth += 1,
+            TokenKind::RParen | TokenKind::RBracket => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return None;
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+
+    let before_ok = start == 0 || opens(&tokens[start - 1]);
+    let after_ok = end == tokens.len() || closes(&tokens[end]);
+    if before_ok && after_ok {
+        Some(start..end)
+    } else {
+        None
+    }
+}
+
+// target を new_node に置き換えた木を作る。target の祖先は終わりの位置だけ、
+// 編集より後ろのノードは始まりと終わりの位置をずらす
+fn splice(ast: &Ast, target: &Ast, new_node: &Ast, edit: &TextEdit) -> Ast {
+    use self::AstKind::*;
+    if std::ptr::eq(ast, target) {
+        return new_node.clone();
+    }
+
+    let loc = if contains(&ast.loc, &target.loc) {
+        Loc(ast.loc.0, edit.shift(ast.loc.1))
+    } else {
+        edit.shift_loc(&ast.loc)
+    };
+    let go = |e: &Ast| splice(e, target, new_node, edit);
+    let go_box = |e: &Ast| Box::new(go(e));
+
+    let value = match &ast.value {
+        Num(n) => Num(*n),
+        Float(x) => Float(*x),
+        Imaginary(x) => Imaginary(*x),
+        Str(s) => Str(s.clone()),
+        Var(name) => Var(name.clone()),
+        Matrix(rows) => Matrix(
+            rows.iter()
+                .map(|row| row.iter().map(go).collect())
+                .collect(),
+        ),
+        WithUnit { e, unit } => WithUnit {
+            e: go_box(e),
+            unit: go_box(unit),
+        },
+        Convert { e, unit } => Convert {
+            e: go_box(e),
+            unit: go_box(unit),
+        },
+        Call { name, args } => Call {
+            name: name.clone(),
+            args: args.iter().map(go).collect(),
+        },
+        UniOp { op, e } => UniOp {
+            op: edit.shift_annot(op),
+            e: go_box(e),
+        },
+        BinOp { op, l, r } => BinOp {
+            op: edit.shift_annot(op),
+            l: go_box(l),
+            r: go_box(r),
+        },
+    };
+    Ast::new(value, loc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    const SOURCES: &[&str] = &[
+        "1 + 2 * 3",
+        "f(10, x * (2 + 3)) - 4",
+        "[1, 2; 3, 4] * [5; 6]",
+        "20 km/h in m/s",
+        "(1 + 2i) * 3.5",
+        "max(1, (2), g(3, 4))",
+    ];
+    const REPLACEMENTS: &[&str] = &["", "7", " ", "+", "(", ")", ",", "x", ".", "i", "12"];
+
+    // 全ての位置で全ての置き換えを試し、最初から字句解析・構文解析した結果と比べる
+    #[test]
+    fn test_incremental_matches_full() {
+        for src in SOURCES {
+            let old_tokens = lex(src).unwrap();
+            let old_ast = parse(old_tokens.clone()).unwrap();
+            for start in 0..=src.len() {
+                for len in 0..=2 {
+                    let end = (start + len).min(src.len());
+                    for text in REPLACEMENTS {
+                        let edit = TextEdit::new(Loc(start, end), *text);
+                        let new_src = edit.apply(src);
+
+                        let full = lex(&new_src);
+                        let inc = relex(src, &old_tokens, &edit);
+                        assert_eq!(
+                            inc.as_ref().map(|r| &r.tokens),
+                            full.as_ref(),
+                            "relex {:?} -> {:?}",
+                            src,
+                            new_src
+                        );
+
+                        if let Ok(tokens) = full {
+                            assert_eq!(
+                                reparse(&old_ast, &old_tokens, &tokens, &edit),
+                                parse(tokens.clone()),
+                                "reparse {:?} -> {:?}",
+                                src,
+                                new_src
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_relex_reuses_tokens() {
+        let src = "f(10, x * (2 + 3)) - 4";
+        let old_tokens = lex(src).unwrap();
+        // "x" を "yy" に置き換えると、先読みの範囲にある "," と yy だけを切り出し直す
+        let edit = TextEdit::new(Loc(6, 7), "yy");
+        let r = relex(src, &old_tokens, &edit).unwrap();
+        assert_eq!(r.relexed, 3..5);
+        assert_eq!(r.tokens[4], Token::ident("yy", Loc(6, 8)));
+        assert_eq!(r.tokens.last(), Some(&Token::number(4, Loc(22, 23))));
+    }
+
+    #[test]
+    fn test_reparse_region() {
+        let src = "f(10, x * (2 + 3)) - 4";
+        let old_tokens = lex(src).unwrap();
+        let old_ast = parse(old_tokens.clone()).unwrap();
+
+        // 括弧の中の編集は括弧の中だけを構文解析し直す
+        let edit = TextEdit::new(Loc(13, 14), "*");
+        let tokens = lex(&edit.apply(src)).unwrap();
+        assert_eq!(
+            reparse_region(&old_ast, &old_tokens, &tokens, &edit),
+            Some(parse(tokens.clone()).unwrap())
+        );
+
+        // 一番外側の二項演算子を変える編集は部分的には解析できない
+        let edit = TextEdit::new(Loc(19, 20), "*");
+        let tokens = lex(&edit.apply(src)).unwrap();
+        assert_eq!(reparse_region(&old_ast, &old_tokens, &tokens, &edit), None);
+        assert_eq!(
+            reparse(&old_ast, &old_tokens, &tokens, &edit),
+            parse(tokens.clone())
+        );
+    }
+}
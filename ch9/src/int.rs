@@ -0,0 +1,462 @@
+use std::fmt;
+
+// 幅を指定した整数の計算（プログラマ電卓のモード）
+// ch3 の wrapping_add / overflowing_add と同じく、桁溢れの扱いを選べる
+
+// 整数の幅と符号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntWidth {
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+}
+
+impl IntWidth {
+    pub const ALL: [IntWidth; 10] = [
+        IntWidth::I8,
+        IntWidth::I16,
+        IntWidth::I32,
+        IntWidth::I64,
+        IntWidth::I128,
+        IntWidth::U8,
+        IntWidth::U16,
+        IntWidth::U32,
+        IntWidth::U64,
+        IntWidth::U128,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntWidth::I8 => "i8",
+            IntWidth::I16 => "i16",
+            IntWidth::I32 => "i32",
+            IntWidth::I64 => "i64",
+            IntWidth::I128 => "i128",
+            IntWidth::U8 => "u8",
+            IntWidth::U16 => "u16",
+            IntWidth::U32 => "u32",
+            IntWidth::U64 => "u64",
+            IntWidth::U128 => "u128",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<IntWidth> {
+        IntWidth::ALL.into_iter().find(|w| w.name() == name)
+    }
+}
+
+// 桁溢れしたときの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    Checked,    // エラーにする
+    Wrapping,   // 2 の補数で折り返す
+    Saturating, // 最大値・最小値に張り付く
+}
+
+impl OverflowPolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            OverflowPolicy::Checked => "checked",
+            OverflowPolicy::Wrapping => "wrapping",
+            OverflowPolicy::Saturating => "saturating",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<OverflowPolicy> {
+        [
+            OverflowPolicy::Checked,
+            OverflowPolicy::Wrapping,
+            OverflowPolicy::Saturating,
+        ]
+        .into_iter()
+        .find(|p| p.name() == name)
+    }
+}
+
+// 整数リテラルを評価するときの幅と桁溢れの扱い。既定は i64 で桁溢れはエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IntMode {
+    pub width: IntWidth,
+    pub policy: OverflowPolicy,
+}
+
+impl Default for IntMode {
+    fn default() -> Self {
+        IntMode {
+            width: IntWidth::I64,
+            policy: OverflowPolicy::Checked,
+        }
+    }
+}
+
+impl fmt::Display for IntMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.width.name(), self.policy.name())
+    }
+}
+
+// 整数の演算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+}
+
+// 整数の表示に使う基数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Radix {
+    #[default]
+    Dec,
+    Hex,
+    Oct,
+    Bin,
+}
+
+impl Radix {
+    pub fn from_name(name: &str) -> Option<Radix> {
+        match name {
+            "dec" => Some(Radix::Dec),
+            "hex" => Some(Radix::Hex),
+            "oct" => Some(Radix::Oct),
+            "bin" => Some(Radix::Bin),
+            _ => None,
+        }
+    }
+}
+
+// 幅ごとの整数型に共通する操作
+trait Prim:
+    Copy
+    + fmt::Display
+    + fmt::LowerHex
+    + fmt::Octal
+    + fmt::Binary
+    + TryFrom<i128>
+    + std::ops::Not<Output = Self>
+{
+    const MIN: Self;
+    const MAX: Self;
+    fn from_bits(bits: u128) -> Self;
+    fn to_bits(self) -> u128;
+    fn to_f64(self) -> f64;
+    fn to_i128(self) -> Option<i128>;
+    fn wrap_i128(n: i128) -> Self;
+    fn shift_amount(self) -> Option<u32>;
+    fn apply(self, op: IntOp, rhs: Self, policy: OverflowPolicy) -> Option<Self>;
+}
+
+macro_rules! impl_prim {
+    ($($t:ty),*) => {$(
+        impl Prim for $t {
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+
+            fn from_bits(bits: u128) -> Self {
+                bits as $t
+            }
+
+            fn to_bits(self) -> u128 {
+                self as u128
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn to_i128(self) -> Option<i128> {
+                i128::try_from(self).ok()
+            }
+
+            fn wrap_i128(n: i128) -> Self {
+                n as $t
+            }
+
+            fn shift_amount(self) -> Option<u32> {
+                u32::try_from(self).ok()
+            }
+
+            fn apply(self, op: IntOp, rhs: Self, policy: OverflowPolicy) -> Option<Self> {
+                use self::IntOp::*;
+                use self::OverflowPolicy::*;
+                match (op, policy) {
+                    (Add, Checked) => self.checked_add(rhs),
+                    (Add, Wrapping) => Some(self.wrapping_add(rhs)),
+                    (Add, Saturating) => Some(self.saturating_add(rhs)),
+                    (Sub, Checked) => self.checked_sub(rhs),
+                    (Sub, Wrapping) => Some(self.wrapping_sub(rhs)),
+                    (Sub, Saturating) => Some(self.saturating_sub(rhs)),
+                    (Mul, Checked) => self.checked_mul(rhs),
+                    (Mul, Wrapping) => Some(self.wrapping_mul(rhs)),
+                    (Mul, Saturating) => Some(self.saturating_mul(rhs)),
+                    // 0 で割るかどうかは呼び出し側で調べる。桁溢れするのは MIN / -1 だけ
+                    (Div, Checked) => self.checked_div(rhs),
+                    (Div, Wrapping) => Some(self.wrapping_div(rhs)),
+                    (Div, Saturating) => Some(self.saturating_div(rhs)),
+                    (And, _) => Some(self & rhs),
+                    (Or, _) => Some(self | rhs),
+                    (Xor, _) => Some(self ^ rhs),
+                    // シフト量が幅以上か負のとき、wrapping は幅で割った余りだけシフトし、
+                    // saturating はすべてのビットを押し出した値にする
+                    (Shl | Shr, _) => {
+                        let amount = rhs.shift_amount();
+                        let shifted = amount.and_then(|n| {
+                            if op == Shl {
+                                self.checked_shl(n)
+                            } else {
+                                self.checked_shr(n)
+                            }
+                        });
+                        match (shifted, policy) {
+                            (Some(v), _) => Some(v),
+                            (None, Checked) => None,
+                            (None, Wrapping) if op == Shl => {
+                                Some(self.wrapping_shl(rhs.to_bits() as u32))
+                            }
+                            (None, Wrapping) => Some(self.wrapping_shr(rhs.to_bits() as u32)),
+                            (None, Saturating) if op == Shl => Some(0),
+                            (None, Saturating) => Some((self >> (<$t>::BITS - 1)) >> 1),
+                        }
+                    }
+                }
+            }
+        }
+    )*};
+}
+
+impl_prim!(i8, i16, i32, i64, i128, u8, u16, u32, u64, u128);
+
+// 幅に応じた整数型を T として式を評価する
+macro_rules! with_width {
+    ($width:expr, $t:ident => $e:expr) => {
+        match $width {
+            IntWidth::I8 => {
+                type $t = i8;
+                $e
+            }
+            IntWidth::I16 => {
+                type $t = i16;
+                $e
+            }
+            IntWidth::I32 => {
+                type $t = i32;
+                $e
+            }
+            IntWidth::I64 => {
+                type $t = i64;
+                $e
+            }
+            IntWidth::I128 => {
+                type $t = i128;
+                $e
+            }
+            IntWidth::U8 => {
+                type $t = u8;
+                $e
+            }
+            IntWidth::U16 => {
+                type $t = u16;
+                $e
+            }
+            IntWidth::U32 => {
+                type $t = u32;
+                $e
+            }
+            IntWidth::U64 => {
+                type $t = u64;
+                $e
+            }
+            IntWidth::U128 => {
+                type $t = u128;
+                $e
+            }
+        }
+    };
+}
+
+// 幅を持つ整数。値は幅の型に変換したビット列として持つ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FixedInt {
+    bits: u128,
+    width: IntWidth,
+}
+
+impl FixedInt {
+    fn new<T: Prim>(v: T, width: IntWidth) -> Self {
+        FixedInt {
+            bits: v.to_bits(),
+            width,
+        }
+    }
+
+    fn get<T: Prim>(self) -> T {
+        T::from_bits(self.bits)
+    }
+
+    pub fn width(self) -> IntWidth {
+        self.width
+    }
+
+    // 整数を mode の幅にする。収まらないときは mode の扱いに従う（checked なら None）
+    pub fn from_i128(n: i128, mode: IntMode) -> Option<FixedInt> {
+        with_width!(mode.width, T => {
+            let v = match (T::try_from(n), mode.policy) {
+                (Ok(v), _) => v,
+                (Err(_), OverflowPolicy::Checked) => return None,
+                (Err(_), OverflowPolicy::Wrapping) => T::wrap_i128(n),
+                (Err(_), OverflowPolicy::Saturating) if n < 0 => <T as Prim>::MIN,
+                (Err(_), OverflowPolicy::Saturating) => <T as Prim>::MAX,
+            };
+            Some(FixedInt::new(v, mode.width))
+        })
+    }
+
+    // u128 の最大値など i128 に収まらない値は None
+    pub fn to_i128(self) -> Option<i128> {
+        with_width!(self.width, T => self.get::<T>().to_i128())
+    }
+
+    pub fn to_f64(self) -> f64 {
+        with_width!(self.width, T => self.get::<T>().to_f64())
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.bits == 0
+    }
+
+    // 同じ幅どうしの演算。桁溢れして policy が checked なら None
+    pub fn apply(self, op: IntOp, rhs: FixedInt, policy: OverflowPolicy) -> Option<FixedInt> {
+        debug_assert_eq!(self.width, rhs.width);
+        with_width!(self.width, T => {
+            self.get::<T>()
+                .apply(op, rhs.get::<T>(), policy)
+                .map(|v| FixedInt::new(v, self.width))
+        })
+    }
+
+    pub fn neg(self, policy: OverflowPolicy) -> Option<FixedInt> {
+        let zero = FixedInt {
+            bits: 0,
+            width: self.width,
+        };
+        zero.apply(IntOp::Sub, self, policy)
+    }
+
+    // 16 進・8 進・2 進では負の数も幅いっぱいの 2 の補数で表示する
+    pub fn to_string_radix(self, radix: Radix) -> String {
+        with_width!(self.width, T => format_radix(self.get::<T>(), radix))
+    }
+}
+
+impl std::ops::Not for FixedInt {
+    type Output = FixedInt;
+
+    fn not(self) -> FixedInt {
+        with_width!(self.width, T => FixedInt::new(!self.get::<T>(), self.width))
+    }
+}
+
+impl fmt::Display for FixedInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        with_width!(self.width, T => self.get::<T>().fmt(f))
+    }
+}
+
+fn format_radix<T: Prim>(v: T, radix: Radix) -> String {
+    match radix {
+        Radix::Dec => v.to_string(),
+        Radix::Hex => format!("{:#x}", v),
+        Radix::Oct => format!("{:#o}", v),
+        Radix::Bin => format!("{:#b}", v),
+    }
+}
+
+// 既定のモード（i64 で桁溢れはエラー）の整数の演算
+pub(crate) fn apply_i64(l: i64, op: IntOp, r: i64) -> Option<i64> {
+    l.apply(op, r, OverflowPolicy::Checked)
+}
+
+// i64 の値を基数つきで表示する（既定のモードの整数）
+pub fn i64_to_string_radix(n: i64, radix: Radix) -> String {
+    format_radix(n, radix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(width: IntWidth, policy: OverflowPolicy) -> IntMode {
+        IntMode { width, policy }
+    }
+
+    #[test]
+    fn test_fixed_int() {
+        use self::IntWidth::*;
+        use self::OverflowPolicy::*;
+
+        let u8 = |n, policy| FixedInt::from_i128(n, mode(U8, policy));
+        assert_eq!(u8(300, Checked), None);
+        assert_eq!(u8(300, Wrapping).unwrap().to_string(), "44");
+        assert_eq!(u8(300, Saturating).unwrap().to_string(), "255");
+        assert_eq!(u8(-1, Saturating).unwrap().to_string(), "0");
+
+        let calc = |width, policy, a, op, b| {
+            let m = mode(width, policy);
+            FixedInt::from_i128(a, m)
+                .unwrap()
+                .apply(op, FixedInt::from_i128(b, m).unwrap(), policy)
+                .map(|v| v.to_string())
+        };
+        assert_eq!(calc(U8, Checked, 200, IntOp::Add, 100), None);
+        assert_eq!(calc(U8, Wrapping, 200, IntOp::Add, 100).unwrap(), "44");
+        assert_eq!(calc(U8, Saturating, 200, IntOp::Add, 100).unwrap(), "255");
+        assert_eq!(calc(I8, Saturating, -100, IntOp::Sub, 100).unwrap(), "-128");
+        assert_eq!(calc(I8, Checked, -128, IntOp::Div, -1), None);
+        assert_eq!(calc(I8, Wrapping, -128, IntOp::Div, -1).unwrap(), "-128");
+        assert_eq!(
+            calc(U16, Checked, 0xf0f0, IntOp::Xor, 0xff00).unwrap(),
+            "4080"
+        );
+        assert_eq!(calc(U8, Checked, 1, IntOp::Shl, 7).unwrap(), "128");
+        assert_eq!(calc(U8, Checked, 1, IntOp::Shl, 8), None);
+        assert_eq!(calc(U8, Wrapping, 1, IntOp::Shl, 9).unwrap(), "2");
+        assert_eq!(calc(U8, Saturating, 1, IntOp::Shl, 9).unwrap(), "0");
+        assert_eq!(calc(I8, Saturating, -64, IntOp::Shr, 20).unwrap(), "-1");
+        assert_eq!(calc(I32, Checked, -8, IntOp::Shr, 1).unwrap(), "-4");
+        assert_eq!(
+            calc(U128, Checked, i128::MAX, IntOp::Mul, 2).unwrap(),
+            (u128::MAX - 1).to_string()
+        );
+
+        let minus_one = FixedInt::from_i128(-1, mode(I16, Checked)).unwrap();
+        assert_eq!(minus_one.to_string_radix(Radix::Hex), "0xffff");
+        assert_eq!((!minus_one).to_string(), "0");
+        assert_eq!(
+            FixedInt::from_i128(5, mode(U8, Checked))
+                .unwrap()
+                .to_string_radix(Radix::Bin),
+            "0b101"
+        );
+        assert_eq!(
+            FixedInt::from_i128(1, mode(U8, Checked))
+                .unwrap()
+                .neg(Wrapping)
+                .unwrap()
+                .to_string(),
+            "255"
+        );
+    }
+}
@@ -0,0 +1,1148 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::complex::Complex;
+use crate::host::HostFn;
+use crate::int::{self, FixedInt, IntMode, IntOp, Radix};
+use crate::lexer::is_reserved;
+use crate::matrix::Matrix;
+use crate::parser::{Ast, AstKind, BinOp, BinOpKind, UniOp, UniOpKind};
+use crate::units::{self, Dim, Quantity, Unit};
+use crate::{Annot, Loc};
+
+// 評価結果の値。整数同士の演算は整数のまま、どちらかが浮動小数点数なら f64 になる
+// 単位つきの値は Quantity、虚部を持つ値は Complex、行列・ベクトルは Matrix になる
+// 文字列は Str で、+ でつなげられる
+// 整数の幅を指定したモード（i64 の checked 以外）では、整数は Fixed になる
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Fixed(FixedInt),
+    Float(f64),
+    Quantity(Quantity),
+    Complex(Complex),
+    Matrix(Matrix),
+    Str(String),
+}
+
+impl Value {
+    // 実数としての値。Quantity は SI 基本単位での値を返す。複素数・行列・文字列は None
+    pub fn to_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::Fixed(n) => Some(n.to_f64()),
+            Value::Float(x) => Some(*x),
+            Value::Quantity(q) => Some(q.value),
+            Value::Complex(_) | Value::Matrix(_) | Value::Str(_) => None,
+        }
+    }
+
+    // to_f64 と同じだが、実数でない値は NaN になる
+    pub fn as_f64(&self) -> f64 {
+        self.to_f64().unwrap_or(f64::NAN)
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Fixed(n) => n.width().name(),
+            Value::Float(_) => "float",
+            Value::Quantity(_) => "quantity",
+            Value::Complex(_) => "complex",
+            Value::Matrix(_) => "matrix",
+            Value::Str(_) => "string",
+        }
+    }
+
+    // 単位を持たない実数（行列の要素やスカラー倍に使える値）
+    fn scalar(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::Fixed(n) => Some(n.to_f64()),
+            Value::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn to_complex(&self) -> Option<Complex> {
+        match self {
+            Value::Complex(c) => Some(*c),
+            v => v.scalar().map(Complex::from),
+        }
+    }
+
+    // 虚部が 0 になった複素数はただの数値に戻す
+    fn from_complex(c: Complex) -> Value {
+        if c.im == 0.0 {
+            Value::Float(c.re)
+        } else {
+            Value::Complex(c)
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Value::Complex(c) => c.is_zero(),
+            Value::Matrix(_) | Value::Str(_) => false,
+            Value::Fixed(n) => n.is_zero(),
+            v => v.as_f64() == 0.0,
+        }
+    }
+
+    pub fn dim(&self) -> Dim {
+        match self {
+            Value::Quantity(q) => q.dim,
+            _ => Dim::NONE,
+        }
+    }
+
+    // 整数は radix の基数で表示する。整数以外は Display と同じ
+    pub fn to_string_radix(&self, radix: Radix) -> String {
+        match self {
+            Value::Int(n) => int::i64_to_string_radix(*n, radix),
+            Value::Fixed(n) => n.to_string_radix(radix),
+            v => v.to_string(),
+        }
+    }
+
+    fn into_quantity(self) -> Quantity {
+        match self {
+            Value::Quantity(q) => q,
+            v => Quantity {
+                value: v.as_f64(),
+                dim: Dim::NONE,
+                unit: None,
+            },
+        }
+    }
+
+    // 次元が打ち消し合った量はただの数値に戻す
+    fn from_quantity(q: Quantity) -> Value {
+        if q.dim.is_none() {
+            Value::Float(q.value)
+        } else {
+            Value::Quantity(q)
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(n) => n.fmt(f),
+            Value::Fixed(n) => n.fmt(f),
+            Value::Float(x) => x.fmt(f),
+            Value::Quantity(q) => q.fmt(f),
+            Value::Complex(c) => c.fmt(f),
+            Value::Matrix(m) => m.fmt(f),
+            Value::Str(s) => s.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InterpreterErrorKind {
+    DivisionByZero,
+    Overflow,
+    UnboundVariable(String),
+    UnknownUnit(String),
+    NotAUnit, // in の右辺が単位の式になっていない
+    DimensionMismatch {
+        left: Dim,
+        right: Dim,
+    },
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    InvalidOperands {
+        left: &'static str,
+        right: &'static str,
+    },
+    ShapeMismatch {
+        left: (usize, usize),
+        right: (usize, usize),
+    },
+    NotSquare((usize, usize)),
+    SingularMatrix,
+    UndefinedFunction(String),
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+    },
+    HostError(String),    // 登録された関数が返したエラー
+    ReservedName(String), // 式から読めない名前（in, i）に変数を定義しようとした
+}
+
+pub type InterpreterError = Annot<InterpreterErrorKind>;
+
+impl fmt::Display for InterpreterErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::InterpreterErrorKind::*;
+        match self {
+            DivisionByZero => write!(f, "division by zero"),
+            Overflow => write!(f, "arithmetic overflow"),
+            UnboundVariable(name) => write!(f, "variable '{}' is not defined", name),
+            UnknownUnit(name) => write!(f, "unknown unit '{}'", name),
+            NotAUnit => write!(f, "expected a unit after 'in'"),
+            DimensionMismatch { left, right } => {
+                write!(f, "dimension mismatch between {} and {}", left, right)
+            }
+            TypeMismatch { expected, found } => {
+                write!(f, "expected {} but found {}", expected, found)
+            }
+            InvalidOperands { left, right } => {
+                write!(f, "operator cannot be applied to {} and {}", left, right)
+            }
+            ShapeMismatch { left, right } => write!(
+                f,
+                "shape mismatch between {}x{} and {}x{}",
+                left.0, left.1, right.0, right.1
+            ),
+            NotSquare((rows, cols)) => {
+                write!(f, "{}x{} matrix is not square", rows, cols)
+            }
+            SingularMatrix => write!(f, "matrix is singular"),
+            UndefinedFunction(name) => write!(f, "function '{}' is not defined", name),
+            ArityMismatch { expected, found } => {
+                write!(f, "expected {} argument(s) but {} given", expected, found)
+            }
+            HostError(msg) => write!(f, "{}", msg),
+            ReservedName(name) => write!(f, "'{}' is a reserved name", name),
+        }
+    }
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.loc, self.value)
+    }
+}
+
+impl std::error::Error for InterpreterError {}
+
+// 引数の型の違いを消して同じ HashMap に入れるためのトレイトオブジェクト
+type BoxedHostFn = Box<dyn Fn(&[Value]) -> Result<Value, InterpreterErrorKind>>;
+
+// 変数と登録された関数を持ち、Ast を評価する
+#[derive(Default)]
+pub struct Interpreter {
+    vars: HashMap<String, Value>,
+    funcs: HashMap<String, BoxedHostFn>,
+    int_mode: IntMode,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            vars: HashMap::new(),
+            funcs: HashMap::new(),
+            int_mode: IntMode::default(),
+        }
+    }
+
+    pub fn int_mode(&self) -> IntMode {
+        self.int_mode
+    }
+
+    // 整数リテラルの幅と桁溢れの扱いを変える。既定は i64 の checked
+    pub fn set_int_mode(&mut self, mode: IntMode) {
+        self.int_mode = mode;
+    }
+
+    // 予約語（lexer::is_reserved）の名前は式から読めないので定義できない
+    pub fn set_var(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Result<(), InterpreterErrorKind> {
+        let name = name.into();
+        if is_reserved(&name) {
+            return Err(InterpreterErrorKind::ReservedName(name));
+        }
+        self.vars.insert(name, value.into());
+        Ok(())
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&Value> {
+        self.vars.get(name)
+    }
+
+    pub fn register_fn<Args, F>(&mut self, name: impl Into<String>, f: F)
+    where
+        F: HostFn<Args> + 'static,
+    {
+        self.funcs
+            .insert(name.into(), Box::new(move |args| f.call(args)));
+    }
+
+    pub fn eval(&mut self, expr: &Ast) -> Result<Value, InterpreterError> {
+        let args = operands(expr)
+            .into_iter()
+            .map(|e| self.eval(e))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.reduce(expr, args)
+    }
+
+    // 値を求めた部分式 args（operands(expr) の順）から expr の値を求める
+    // 1段ずつ評価する（explain）ときにも使う
+    pub(crate) fn reduce(
+        &mut self,
+        expr: &Ast,
+        mut args: Vec<Value>,
+    ) -> Result<Value, InterpreterError> {
+        use self::AstKind::*;
+        match &expr.value {
+            // 既定のモードと同じ計算になるので、i64 の checked では Int のまま
+            Num(n) if self.int_mode == IntMode::default() => {
+                i64::try_from(*n).map(Value::Int).map_err(|_| {
+                    InterpreterError::new(InterpreterErrorKind::Overflow, expr.loc.clone())
+                })
+            }
+            Num(n) => FixedInt::from_i128(*n as i128, self.int_mode)
+                .map(Value::Fixed)
+                .ok_or_else(|| {
+                    InterpreterError::new(InterpreterErrorKind::Overflow, expr.loc.clone())
+                }),
+            Float(x) => Ok(Value::Float(*x)),
+            Imaginary(x) => Ok(Value::Complex(Complex::new(0.0, *x))),
+            Str(s) => Ok(Value::Str(s.clone())),
+            Matrix(rows) => eval_matrix(rows, args, &expr.loc),
+            Var(name) => self.vars.get(name).cloned().ok_or_else(|| {
+                InterpreterError::new(
+                    InterpreterErrorKind::UnboundVariable(name.clone()),
+                    expr.loc.clone(),
+                )
+            }),
+            WithUnit { unit, .. } => {
+                let n = args.remove(0);
+                let unit = self.eval_unit(unit)?;
+                Ok(Value::Quantity(Quantity::new(n.as_f64(), unit)))
+            }
+            Convert { e, unit } => {
+                let q = expect_real(args.remove(0), &e.loc)?.into_quantity();
+                let unit = self.eval_unit(unit)?;
+                if q.dim != unit.dim {
+                    return Err(InterpreterError::new(
+                        InterpreterErrorKind::DimensionMismatch {
+                            left: q.dim,
+                            right: unit.dim,
+                        },
+                        expr.loc.clone(),
+                    ));
+                }
+                Ok(Value::Quantity(Quantity {
+                    unit: Some(unit),
+                    ..q
+                }))
+            }
+            Call { name, .. } => {
+                // 登録された関数を優先し、なければ組み込み関数を探す
+                let result = match self.funcs.get(name) {
+                    Some(f) => f(&args),
+                    None => builtin(name, &args).unwrap_or_else(|| {
+                        Err(InterpreterErrorKind::UndefinedFunction(name.clone()))
+                    }),
+                };
+                // 関数側のエラーには呼び出し箇所の位置情報をつける
+                result.map_err(|e| InterpreterError::new(e, expr.loc.clone()))
+            }
+            UniOp { op, .. } => {
+                let e = args.remove(0);
+                self.eval_uniop(op, e, &expr.loc)
+            }
+            BinOp { op, .. } => {
+                let r = args.pop().unwrap();
+                let l = args.pop().unwrap();
+                self.eval_binop(op, l, r, &expr.loc)
+            }
+        }
+    }
+
+    fn eval_uniop(&mut self, op: &UniOp, n: Value, loc: &Loc) -> Result<Value, InterpreterError> {
+        use self::UniOpKind::*;
+        let overflow = || InterpreterError::new(InterpreterErrorKind::Overflow, loc.clone());
+        match (&op.value, n) {
+            (Plus, n) => Ok(n),
+            (Not, Value::Int(n)) => Ok(Value::Int(!n)),
+            (Not, Value::Fixed(n)) => Ok(Value::Fixed(!n)),
+            (Not, v) => Err(InterpreterError::new(
+                InterpreterErrorKind::TypeMismatch {
+                    expected: "int",
+                    found: v.type_name(),
+                },
+                loc.clone(),
+            )),
+            (Minus, Value::Fixed(n)) => n
+                .neg(self.int_mode.policy)
+                .map(Value::Fixed)
+                .ok_or_else(overflow),
+            (Minus, Value::Int(n)) => n.checked_neg().map(Value::Int).ok_or_else(overflow),
+            (Minus, Value::Float(x)) => Ok(Value::Float(-x)),
+            (Minus, Value::Quantity(q)) => Ok(Value::Quantity(Quantity {
+                value: -q.value,
+                ..q
+            })),
+            (Minus, Value::Complex(c)) => Ok(Value::Complex(-c)),
+            (Minus, Value::Matrix(m)) => Ok(Value::Matrix(m.map(|x| -x))),
+            (Minus, Value::Str(_)) => Err(InterpreterError::new(
+                InterpreterErrorKind::TypeMismatch {
+                    expected: "number",
+                    found: "string",
+                },
+                loc.clone(),
+            )),
+        }
+    }
+
+    fn eval_binop(
+        &mut self,
+        op: &BinOp,
+        l: Value,
+        r: Value,
+        loc: &Loc,
+    ) -> Result<Value, InterpreterError> {
+        use self::BinOpKind::*;
+        let overflow = || InterpreterError::new(InterpreterErrorKind::Overflow, loc.clone());
+
+        if r.is_zero() && op.value == Div {
+            return Err(InterpreterError::new(
+                InterpreterErrorKind::DivisionByZero,
+                loc.clone(),
+            ));
+        }
+
+        let int_op = match op.value {
+            Add => IntOp::Add,
+            Sub => IntOp::Sub,
+            Mult => IntOp::Mul,
+            Div => IntOp::Div,
+            BitAnd => IntOp::And,
+            BitOr => IntOp::Or,
+            BitXor => IntOp::Xor,
+            Shl => IntOp::Shl,
+            Shr => IntOp::Shr,
+        };
+        match (l, r) {
+            // 整数同士は桁溢れを検査する
+            (Value::Int(l), Value::Int(r)) => int::apply_i64(l, int_op, r)
+                .map(Value::Int)
+                .ok_or_else(overflow),
+            // 幅を持つ整数は同じ幅どうしで計算する。Int（登録した変数など）は相手の幅にそろえる
+            (l @ Value::Fixed(_), r @ (Value::Int(_) | Value::Fixed(_)))
+            | (l @ Value::Int(_), r @ Value::Fixed(_)) => {
+                let (a, b) = self
+                    .fixed_operands(&l, &r)
+                    .map_err(|e| InterpreterError::new(e, loc.clone()))?;
+                a.apply(int_op, b, self.int_mode.policy)
+                    .map(Value::Fixed)
+                    .ok_or_else(overflow)
+            }
+            // ビット演算は整数にしか使えない
+            (l, r) if op.value.is_bitwise() => {
+                Err(InterpreterError::new(invalid_operands(&l, &r), loc.clone()))
+            }
+            // 文字列は + で連結だけできる
+            (Value::Str(l), Value::Str(r)) if op.value == Add => Ok(Value::Str(l + &r)),
+            (l @ Value::Str(_), r) | (l, r @ Value::Str(_)) => {
+                Err(InterpreterError::new(invalid_operands(&l, &r), loc.clone()))
+            }
+            (l @ Value::Matrix(_), r) | (l, r @ Value::Matrix(_)) => {
+                eval_matrix_binop(op, l, r).map_err(|e| InterpreterError::new(e, loc.clone()))
+            }
+            (l @ Value::Complex(_), r) | (l, r @ Value::Complex(_)) => {
+                let (a, b) = match (l.to_complex(), r.to_complex()) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => return Err(InterpreterError::new(invalid_operands(&l, &r), loc.clone())),
+                };
+                Ok(Value::from_complex(match op.value {
+                    Add => a + b,
+                    Sub => a - b,
+                    Mult => a * b,
+                    Div => a / b,
+                    _ => unreachable!("bitwise operators are only for integers"),
+                }))
+            }
+            (l, r) if l.dim().is_none() && r.dim().is_none() => {
+                let (l, r) = (l.as_f64(), r.as_f64());
+                Ok(Value::Float(match op.value {
+                    Add => l + r,
+                    Sub => l - r,
+                    Mult => l * r,
+                    Div => l / r,
+                    _ => unreachable!("bitwise operators are only for integers"),
+                }))
+            }
+            (l, r) => self.eval_quantity_binop(op, l.into_quantity(), r.into_quantity(), loc),
+        }
+    }
+
+    // l と r を同じ幅の整数にそろえる。どちらかは Fixed
+    fn fixed_operands(
+        &self,
+        l: &Value,
+        r: &Value,
+    ) -> Result<(FixedInt, FixedInt), InterpreterErrorKind> {
+        let width = match (l, r) {
+            (Value::Fixed(n), _) | (_, Value::Fixed(n)) => n.width(),
+            _ => unreachable!(),
+        };
+        let mode = IntMode {
+            width,
+            policy: self.int_mode.policy,
+        };
+        let convert = |v: &Value| match v {
+            Value::Fixed(n) if n.width() == width => Ok(*n),
+            Value::Int(n) => {
+                FixedInt::from_i128(*n as i128, mode).ok_or(InterpreterErrorKind::Overflow)
+            }
+            _ => Err(invalid_operands(l, r)),
+        };
+        Ok((convert(l)?, convert(r)?))
+    }
+
+    // 単位つきの演算。足し算・引き算は次元が一致している必要があり、
+    // 掛け算・割り算は次元も掛け算・割り算される
+    fn eval_quantity_binop(
+        &mut self,
+        op: &BinOp,
+        l: Quantity,
+        r: Quantity,
+        loc: &Loc,
+    ) -> Result<Value, InterpreterError> {
+        use self::BinOpKind::*;
+        match op.value {
+            Add | Sub => {
+                if l.dim != r.dim {
+                    return Err(InterpreterError::new(
+                        InterpreterErrorKind::DimensionMismatch {
+                            left: l.dim,
+                            right: r.dim,
+                        },
+                        loc.clone(),
+                    ));
+                }
+                let value = if op.value == Add {
+                    l.value + r.value
+                } else {
+                    l.value - r.value
+                };
+                Ok(Value::from_quantity(Quantity {
+                    value,
+                    dim: l.dim,
+                    unit: l.unit.or(r.unit),
+                }))
+            }
+            Mult | Div => {
+                let (value, dim) = if op.value == Mult {
+                    (l.value * r.value, l.dim.mul(&r.dim))
+                } else {
+                    (l.value / r.value, l.dim.div(&r.dim))
+                };
+                let dim = dim.ok_or_else(|| {
+                    InterpreterError::new(InterpreterErrorKind::Overflow, op.loc.clone())
+                })?;
+                // 表示用の単位を組み立てる。片方が無次元の数なら単位はそのまま
+                let unit = match (l.unit, r.unit) {
+                    (Some(lu), Some(ru)) if op.value == Mult => lu.mul(&ru),
+                    (Some(lu), Some(ru)) => lu.div(&ru),
+                    (Some(lu), None) if r.dim.is_none() => Some(lu),
+                    (None, Some(ru)) if l.dim.is_none() && op.value == Mult => Some(ru),
+                    (None, Some(ru)) if l.dim.is_none() => Unit::new("1", 1.0, Dim::NONE).div(&ru),
+                    _ => None,
+                };
+                Ok(Value::from_quantity(Quantity { value, dim, unit }))
+            }
+            _ => unreachable!("bitwise operators are only for integers"),
+        }
+    }
+
+    // 単位つきリテラルの単位と in の右辺を評価する。変数は見ずに単位表だけを引く
+    fn eval_unit(&mut self, expr: &Ast) -> Result<Unit, InterpreterError> {
+        match &expr.value {
+            AstKind::Var(name) => lookup_unit(name, &expr.loc),
+            AstKind::BinOp { op, l, r } if matches!(op.value, BinOpKind::Mult | BinOpKind::Div) => {
+                let (l, r) = (self.eval_unit(l)?, self.eval_unit(r)?);
+                let unit = if op.value == BinOpKind::Mult {
+                    l.mul(&r)
+                } else {
+                    l.div(&r)
+                };
+                unit.ok_or_else(|| {
+                    InterpreterError::new(InterpreterErrorKind::Overflow, op.loc.clone())
+                })
+            }
+            _ => Err(InterpreterError::new(
+                InterpreterErrorKind::NotAUnit,
+                expr.loc.clone(),
+            )),
+        }
+    }
+}
+
+// 値を求める部分式。単位は単位表から引くので含めない
+pub(crate) fn operands(expr: &Ast) -> Vec<&Ast> {
+    match &expr.value {
+        AstKind::WithUnit { e, .. } | AstKind::Convert { e, .. } => vec![e],
+        _ => expr.children(),
+    }
+}
+
+// 行列リテラル。要素は単位を持たない実数で、各行の長さが揃っている必要がある
+fn eval_matrix(
+    rows: &[Vec<Ast>],
+    values: Vec<Value>,
+    loc: &Loc,
+) -> Result<Value, InterpreterError> {
+    let cols = rows[0].len();
+    if let Some(row) = rows.iter().find(|row| row.len() != cols) {
+        return Err(InterpreterError::new(
+            InterpreterErrorKind::ShapeMismatch {
+                left: (1, cols),
+                right: (1, row.len()),
+            },
+            loc.clone(),
+        ));
+    }
+    let mut data = Vec::with_capacity(values.len());
+    for (e, v) in rows.iter().flatten().zip(values) {
+        let x = v.scalar().ok_or_else(|| {
+            InterpreterError::new(
+                InterpreterErrorKind::TypeMismatch {
+                    expected: "real",
+                    found: v.type_name(),
+                },
+                e.loc.clone(),
+            )
+        })?;
+        data.push(x);
+    }
+    Ok(Value::Matrix(Matrix::new(rows.len(), cols, data)))
+}
+
+// 行列を含む演算。行列同士の + - は要素ごと、* は行列積
+// 行列とスカラーの演算は各要素にスカラーを適用する
+fn eval_matrix_binop(op: &BinOp, l: Value, r: Value) -> Result<Value, InterpreterErrorKind> {
+    use self::BinOpKind::*;
+    let apply = |a: f64, b: f64| match op.value {
+        Add => a + b,
+        Sub => a - b,
+        Mult => a * b,
+        Div => a / b,
+        _ => unreachable!("bitwise operators are only for integers"),
+    };
+    match (&l, &r) {
+        (Value::Matrix(a), Value::Matrix(b)) => match op.value {
+            Add | Sub => a.zip_with(b, apply).map(Value::Matrix),
+            Mult => a.matmul(b).map(Value::Matrix),
+            _ => Err(invalid_operands(&l, &r)),
+        },
+        (Value::Matrix(a), s) => match s.scalar() {
+            Some(s) => Ok(Value::Matrix(a.map(|x| apply(x, s)))),
+            None => Err(invalid_operands(&l, &r)),
+        },
+        (s, Value::Matrix(b)) => match s.scalar() {
+            Some(s) => Ok(Value::Matrix(b.map(|x| apply(s, x)))),
+            None => Err(invalid_operands(&l, &r)),
+        },
+        _ => unreachable!(),
+    }
+}
+
+// 組み込み関数。見つからなければ None
+fn builtin(name: &str, args: &[Value]) -> Option<Result<Value, InterpreterErrorKind>> {
+    if name == "format" {
+        return Some(format(args));
+    }
+    let f: fn(&Value) -> Result<Value, InterpreterErrorKind> = match name {
+        "len" => |v| expect_str(v).map(|s| Value::Int(s.chars().count() as i64)),
+        "upper" => |v| expect_str(v).map(|s| Value::Str(s.to_uppercase())),
+        "transpose" => |v| expect_matrix(v).map(|m| Value::Matrix(m.transpose())),
+        "det" => |v| expect_matrix(v)?.det().map(Value::Float),
+        "inv" => |v| expect_matrix(v)?.inverse().map(Value::Matrix),
+        "re" => |v| expect_complex(v).map(|c| Value::Float(c.re)),
+        "im" => |v| expect_complex(v).map(|c| Value::Float(c.im)),
+        "conj" => |v| expect_complex(v).map(|c| Value::from_complex(c.conj())),
+        "abs" => |v| match v {
+            Value::Int(n) => n
+                .checked_abs()
+                .map(Value::Int)
+                .ok_or(InterpreterErrorKind::Overflow),
+            Value::Quantity(q) => Ok(Value::Quantity(Quantity {
+                value: q.value.abs(),
+                ..q.clone()
+            })),
+            v => expect_complex(v).map(|c| Value::Float(c.abs())),
+        },
+        _ => return None,
+    };
+    if args.len() != 1 {
+        return Some(Err(InterpreterErrorKind::ArityMismatch {
+            expected: 1,
+            found: args.len(),
+        }));
+    }
+    Some(f(&args[0]))
+}
+
+// format("{} = {}", a, b)。{} を順に引数の表示で置き換える。{{ と }} は { と } になる
+fn format(args: &[Value]) -> Result<Value, InterpreterErrorKind> {
+    let (template, args) = match args.split_first() {
+        Some((template, args)) => (expect_str(template)?, args),
+        None => {
+            return Err(InterpreterErrorKind::ArityMismatch {
+                expected: 1,
+                found: 0,
+            })
+        }
+    };
+    // {} で区切った文字列の断片
+    let mut parts = vec![String::new()];
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                parts.last_mut().unwrap().push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                parts.push(String::new());
+            }
+            _ => parts.last_mut().unwrap().push(c),
+        }
+    }
+    if parts.len() - 1 != args.len() {
+        return Err(InterpreterErrorKind::ArityMismatch {
+            expected: parts.len(),
+            found: args.len() + 1,
+        });
+    }
+    let mut out = parts[0].clone();
+    for (arg, part) in args.iter().zip(&parts[1..]) {
+        out.push_str(&arg.to_string());
+        out.push_str(part);
+    }
+    Ok(Value::Str(out))
+}
+
+fn expect_str(v: &Value) -> Result<&str, InterpreterErrorKind> {
+    match v {
+        Value::Str(s) => Ok(s),
+        v => Err(InterpreterErrorKind::TypeMismatch {
+            expected: "string",
+            found: v.type_name(),
+        }),
+    }
+}
+
+fn expect_matrix(v: &Value) -> Result<&Matrix, InterpreterErrorKind> {
+    match v {
+        Value::Matrix(m) => Ok(m),
+        v => Err(InterpreterErrorKind::TypeMismatch {
+            expected: "matrix",
+            found: v.type_name(),
+        }),
+    }
+}
+
+fn expect_complex(v: &Value) -> Result<Complex, InterpreterErrorKind> {
+    v.to_complex().ok_or(InterpreterErrorKind::TypeMismatch {
+        expected: "complex",
+        found: v.type_name(),
+    })
+}
+
+// 複素数・行列を受け付けない場所（単位変換など）で使う
+fn expect_real(v: Value, loc: &Loc) -> Result<Value, InterpreterError> {
+    match v {
+        Value::Complex(_) | Value::Matrix(_) | Value::Str(_) => Err(InterpreterError::new(
+            InterpreterErrorKind::TypeMismatch {
+                expected: "real",
+                found: v.type_name(),
+            },
+            loc.clone(),
+        )),
+        v => Ok(v),
+    }
+}
+
+fn invalid_operands(l: &Value, r: &Value) -> InterpreterErrorKind {
+    InterpreterErrorKind::InvalidOperands {
+        left: l.type_name(),
+        right: r.type_name(),
+    }
+}
+
+fn lookup_unit(name: &str, loc: &Loc) -> Result<Unit, InterpreterError> {
+    units::lookup(name).ok_or_else(|| {
+        InterpreterError::new(
+            InterpreterErrorKind::UnknownUnit(name.to_string()),
+            loc.clone(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex, parse};
+
+    fn eval(input: &str) -> Result<Value, InterpreterError> {
+        Interpreter::new().eval(&parse(lex(input).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_interpreter() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(Value::Int(7)));
+        assert_eq!(eval("(1 + 2) * -3"), Ok(Value::Int(-9)));
+        assert_eq!(eval("7 / 2"), Ok(Value::Int(3)));
+        assert_eq!(
+            eval("1 + 2 / (3 - 3)"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DivisionByZero,
+                Loc(4, 14)
+            ))
+        );
+        assert_eq!(
+            eval("9223372036854775807 + 1"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::Overflow,
+                Loc(0, 23)
+            ))
+        );
+        assert_eq!(
+            eval("1 + x"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::UnboundVariable("x".to_string()),
+                Loc(4, 5)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_complex_and_matrix() {
+        let show = |input| eval(input).unwrap().to_string();
+        assert_eq!(show("(1 + 2i) * (3 - i)"), "5+5i");
+        assert_eq!(show("i * i"), "-1");
+        assert_eq!(show("(4 + 2i) / 2i"), "1-2i");
+        assert_eq!(show("abs(3 + 4i)"), "5");
+        assert_eq!(show("conj(1 + i)"), "1-1i");
+        assert_eq!(show("[1, 2; 3, 4] + [1, 1; 1, 1]"), "[2, 3; 4, 5]");
+        assert_eq!(show("2 * [1, 2; 3, 4] - 1"), "[1, 3; 5, 7]");
+        assert_eq!(show("[1, 2; 3, 4] * [5; 6]"), "[17; 39]");
+        assert_eq!(show("transpose([1, 2, 3])"), "[1; 2; 3]");
+        assert_eq!(show("det([1, 2; 3, 4])"), "-2");
+        assert_eq!(show("inv([2, 0; 0, 4])"), "[0.5, 0; 0, 0.25]");
+        assert_eq!(
+            eval("[1, 2] + [1, 2, 3]"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::ShapeMismatch {
+                    left: (1, 2),
+                    right: (1, 3)
+                },
+                Loc(0, 18)
+            ))
+        );
+        assert_eq!(
+            eval("1 + [1, 2; 3]"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::ShapeMismatch {
+                    left: (1, 2),
+                    right: (1, 1)
+                },
+                Loc(4, 13)
+            ))
+        );
+        assert_eq!(
+            eval("det([1, 2])"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::NotSquare((1, 2)),
+                Loc(0, 11)
+            ))
+        );
+        assert_eq!(
+            eval("inv([1, 2; 2, 4])"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::SingularMatrix,
+                Loc(0, 17)
+            ))
+        );
+        assert_eq!(
+            eval("[1, 2i]"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::TypeMismatch {
+                    expected: "real",
+                    found: "complex"
+                },
+                Loc(4, 6)
+            ))
+        );
+        assert_eq!(
+            eval("2 m + i"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::InvalidOperands {
+                    left: "quantity",
+                    right: "complex"
+                },
+                Loc(0, 7)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        let show = |input| eval(input).unwrap().to_string();
+        assert_eq!(show("3 m"), "3 m");
+        assert_eq!(show("20 km/h"), "20 km/h");
+        assert_eq!(show("36 km/h in m/s"), "10 m/s");
+        assert_eq!(show("1.5 km + 500 m"), "2 km");
+        assert_eq!(show("2 * 3 s"), "6 s");
+        assert_eq!(show("10 m / 2 s"), "5 m/s");
+        assert_eq!(show("1 / 4 s"), "0.25 1/s");
+        assert_eq!(show("(2 m) * (3 m) in cm2"), "60000 cm2");
+        assert_eq!(show("90 min in h"), "1.5 h");
+        assert_eq!(show("6 m / 3 m"), "2");
+        assert_eq!(show("2 kg * 10 m/s2 in N"), "20 N");
+        assert_eq!(
+            eval("3 m + 2 s"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DimensionMismatch {
+                    left: Dim::LENGTH,
+                    right: Dim::TIME
+                },
+                Loc(0, 9)
+            ))
+        );
+        assert_eq!(
+            eval("1 + 3 m"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DimensionMismatch {
+                    left: Dim::NONE,
+                    right: Dim::LENGTH
+                },
+                Loc(0, 7)
+            ))
+        );
+        assert_eq!(
+            eval("3 m in s"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DimensionMismatch {
+                    left: Dim::LENGTH,
+                    right: Dim::TIME
+                },
+                Loc(0, 8)
+            ))
+        );
+        assert_eq!(show("1.5e3 m in km"), "1.5 km");
+        assert_eq!(
+            eval("3 km/parsec"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::UnknownUnit("parsec".to_string()),
+                Loc(5, 11)
+            ))
+        );
+        // 次元の指数が i8 に収まらなければ、演算子の位置で桁溢れにする
+        assert_eq!(
+            eval("1 m100 * 1 m100"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::Overflow,
+                Loc(7, 8)
+            ))
+        );
+        assert_eq!(
+            eval("1 m100*m100"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::Overflow,
+                Loc(6, 7)
+            ))
+        );
+        assert_eq!(
+            eval("3 m in 2"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::NotAUnit,
+                Loc(7, 8)
+            ))
+        );
+    }
+
+    // 単位と変数は別の名前として扱う
+    #[test]
+    fn test_units_and_variables() {
+        let mut interp = Interpreter::new();
+        interp.set_var("h", 2).unwrap();
+        interp.set_var("t", 4).unwrap();
+        let mut show = |input| {
+            interp
+                .eval(&parse(lex(input).unwrap()).unwrap())
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(show("20 km/h in m/s"), "5.555555555555555 m/s");
+        assert_eq!(show("h * 3"), "6");
+        // 空白を挟んだ / は変数での割り算になる
+        assert_eq!(show("8 m / t"), "2 m");
+        assert_eq!(
+            eval("1 + s"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::UnboundVariable("s".to_string()),
+                Loc(4, 5)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let show = |input| eval(input).unwrap().to_string();
+        assert_eq!(show(r#""speed: " + "fast""#), "speed: fast");
+        assert_eq!(show(r#"len("héllo")"#), "5");
+        assert_eq!(show(r#"upper("abc") + "\tx""#), "ABC\tx");
+        assert_eq!(
+            show(r#"format("{} = {} ({{}})", "v", 36 km/h in m/s)"#),
+            "v = 10 m/s ({})"
+        );
+        assert_eq!(
+            eval(r#""a" + 1"#),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::InvalidOperands {
+                    left: "string",
+                    right: "int"
+                },
+                Loc(0, 7)
+            ))
+        );
+        assert_eq!(
+            eval(r#"format("{} {}", 1)"#),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::ArityMismatch {
+                    expected: 3,
+                    found: 2
+                },
+                Loc(0, 18)
+            ))
+        );
+        assert_eq!(
+            eval("len(3)"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::TypeMismatch {
+                    expected: "string",
+                    found: "int"
+                },
+                Loc(0, 6)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_bitwise_and_int_modes() {
+        use crate::int::{IntWidth, OverflowPolicy};
+
+        let show = |input| eval(input).unwrap().to_string();
+        assert_eq!(show("0xf0 | 0x0f & 0x3c"), "252");
+        assert_eq!(show("6 ^ 3"), "5");
+        assert_eq!(show("~0"), "-1");
+        assert_eq!(show("1 << 4 + 1"), "32");
+        assert_eq!(show("-16 >> 2"), "-4");
+        assert_eq!(
+            eval("1 << 64"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::Overflow,
+                Loc(0, 7)
+            ))
+        );
+        assert_eq!(
+            eval("1.5 & 1"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::InvalidOperands {
+                    left: "float",
+                    right: "int"
+                },
+                Loc(0, 7)
+            ))
+        );
+        assert_eq!(
+            eval("-1").unwrap().to_string_radix(Radix::Hex),
+            "0xffffffffffffffff"
+        );
+
+        let eval_in = |width, policy, input| {
+            let mut interp = Interpreter::new();
+            interp.set_int_mode(IntMode { width, policy });
+            interp.eval(&parse(lex(input).unwrap()).unwrap())
+        };
+        let show_in = |width, policy, input| eval_in(width, policy, input).unwrap().to_string();
+        assert_eq!(
+            show_in(IntWidth::U8, OverflowPolicy::Wrapping, "200 + 100"),
+            "44"
+        );
+        assert_eq!(show_in(IntWidth::U8, OverflowPolicy::Wrapping, "-1"), "255");
+        assert_eq!(
+            show_in(IntWidth::U8, OverflowPolicy::Saturating, "200 + 100"),
+            "255"
+        );
+        assert_eq!(
+            show_in(IntWidth::I8, OverflowPolicy::Saturating, "-100 - 100"),
+            "-128"
+        );
+        assert_eq!(
+            show_in(IntWidth::U8, OverflowPolicy::Checked, "~0x0f"),
+            "240"
+        );
+        assert_eq!(
+            show_in(IntWidth::U8, OverflowPolicy::Checked, "255 / 2 * 1.0"),
+            "127"
+        );
+        assert_eq!(
+            eval_in(IntWidth::U8, OverflowPolicy::Checked, "200 + 100"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::Overflow,
+                Loc(0, 9)
+            ))
+        );
+        assert_eq!(
+            eval_in(IntWidth::U8, OverflowPolicy::Checked, "256"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::Overflow,
+                Loc(0, 3)
+            ))
+        );
+        assert_eq!(
+            show_in(
+                IntWidth::U128,
+                OverflowPolicy::Checked,
+                "18446744073709551615 * 18446744073709551615"
+            ),
+            "340282366920938463426481119284349108225"
+        );
+        assert_eq!(
+            eval_in(IntWidth::I16, OverflowPolicy::Checked, "-1")
+                .unwrap()
+                .to_string_radix(Radix::Bin),
+            "0b1111111111111111"
+        );
+    }
+}
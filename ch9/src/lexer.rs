@@ -0,0 +1,629 @@
+use std::fmt;
+
+use crate::{Annot, Loc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Number(u64),
+    Float(f64),     // 小数点か指数を含む数値リテラル
+    Imaginary(f64), // 虚数リテラル（2i, i）
+    Ident(String),
+    Str(String), // 文字列リテラル（エスケープを解決した中身）
+    In,          // 単位変換のキーワード in
+    Plus,
+    Minus,
+    Asterisk,
+    Slash,
+    Ampersand, // &
+    Pipe,      // |
+    Caret,     // ^
+    Shl,       // <<
+    Shr,       // >>
+    Tilde,     // ~
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Semicolon,
+}
+
+pub type Token = Annot<TokenKind>;
+impl Token {
+    //型エイリアスにも実装つけられる
+    pub fn number(n: u64, loc: Loc) -> Self {
+        Self::new(TokenKind::Number(n), loc) // Token { value: TokenKind::Number(n), loc }
+    }
+    pub fn float(x: f64, loc: Loc) -> Self {
+        Self::new(TokenKind::Float(x), loc)
+    }
+    pub fn imaginary(x: f64, loc: Loc) -> Self {
+        Self::new(TokenKind::Imaginary(x), loc)
+    }
+    pub fn string(s: impl Into<String>, loc: Loc) -> Self {
+        Self::new(TokenKind::Str(s.into()), loc)
+    }
+    pub fn kw_in(loc: Loc) -> Self {
+        Self::new(TokenKind::In, loc)
+    }
+    pub fn ident(s: impl Into<String>, loc: Loc) -> Self {
+        Self::new(TokenKind::Ident(s.into()), loc)
+    }
+    pub fn plus(loc: Loc) -> Self {
+        Self::new(TokenKind::Plus, loc)
+    }
+    pub fn minus(loc: Loc) -> Self {
+        Self::new(TokenKind::Minus, loc)
+    }
+    pub fn asterisk(loc: Loc) -> Self {
+        Self::new(TokenKind::Asterisk, loc)
+    }
+    pub fn slash(loc: Loc) -> Self {
+        Self::new(TokenKind::Slash, loc)
+    }
+    pub fn ampersand(loc: Loc) -> Self {
+        Self::new(TokenKind::Ampersand, loc)
+    }
+    pub fn pipe(loc: Loc) -> Self {
+        Self::new(TokenKind::Pipe, loc)
+    }
+    pub fn caret(loc: Loc) -> Self {
+        Self::new(TokenKind::Caret, loc)
+    }
+    pub fn shl(loc: Loc) -> Self {
+        Self::new(TokenKind::Shl, loc)
+    }
+    pub fn shr(loc: Loc) -> Self {
+        Self::new(TokenKind::Shr, loc)
+    }
+    pub fn tilde(loc: Loc) -> Self {
+        Self::new(TokenKind::Tilde, loc)
+    }
+    pub fn lparen(loc: Loc) -> Self {
+        Self::new(TokenKind::LParen, loc)
+    }
+    pub fn rparen(loc: Loc) -> Self {
+        Self::new(TokenKind::RParen, loc)
+    }
+    pub fn lbracket(loc: Loc) -> Self {
+        Self::new(TokenKind::LBracket, loc)
+    }
+    pub fn rbracket(loc: Loc) -> Self {
+        Self::new(TokenKind::RBracket, loc)
+    }
+    pub fn comma(loc: Loc) -> Self {
+        Self::new(TokenKind::Comma, loc)
+    }
+    pub fn semicolon(loc: Loc) -> Self {
+        Self::new(TokenKind::Semicolon, loc)
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::TokenKind::*;
+        match self {
+            Number(n) => n.fmt(f),
+            Float(x) => x.fmt(f),
+            Imaginary(x) => write!(f, "{}i", x),
+            Ident(s) => s.fmt(f),
+            Str(s) => write!(f, "{:?}", s),
+            In => write!(f, "in"),
+            Plus => write!(f, "+"),
+            Minus => write!(f, "-"),
+            Asterisk => write!(f, "*"),
+            Slash => write!(f, "/"),
+            Ampersand => write!(f, "&"),
+            Pipe => write!(f, "|"),
+            Caret => write!(f, "^"),
+            Shl => write!(f, "<<"),
+            Shr => write!(f, ">>"),
+            Tilde => write!(f, "~"),
+            LParen => write!(f, "("),
+            RParen => write!(f, ")"),
+            LBracket => write!(f, "["),
+            RBracket => write!(f, "]"),
+            Comma => write!(f, ","),
+            Semicolon => write!(f, ";"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LexErrorKind {
+    InvalidChar(char),
+    NumberOverflow,      // u64 に収まらない数値リテラル
+    UnterminatedString,  // 閉じる " がない文字列リテラル
+    InvalidEscape(char), // \ の後に使えない文字が続いた
+    MissingDigits,       // 0x, 0o, 0b の後に桁がない
+    Eof,
+}
+
+pub type LexError = Annot<LexErrorKind>;
+impl LexError {
+    pub fn invalid_char(c: char, loc: Loc) -> Self {
+        Self::new(LexErrorKind::InvalidChar(c), loc)
+    }
+    pub fn number_overflow(loc: Loc) -> Self {
+        Self::new(LexErrorKind::NumberOverflow, loc)
+    }
+    pub fn unterminated_string(loc: Loc) -> Self {
+        Self::new(LexErrorKind::UnterminatedString, loc)
+    }
+    pub fn invalid_escape(c: char, loc: Loc) -> Self {
+        Self::new(LexErrorKind::InvalidEscape(c), loc)
+    }
+    pub fn missing_digits(loc: Loc) -> Self {
+        Self::new(LexErrorKind::MissingDigits, loc)
+    }
+    pub fn eof(loc: Loc) -> Self {
+        Self::new(LexErrorKind::Eof, loc)
+    }
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::LexErrorKind::*;
+        match self {
+            InvalidChar(c) => write!(f, "invalid char '{}'", c),
+            NumberOverflow => write!(f, "number literal is too large"),
+            UnterminatedString => write!(f, "string literal is not terminated"),
+            InvalidEscape(c) => write!(f, "invalid escape sequence '\\{}'", c),
+            MissingDigits => write!(f, "number literal has no digits after the prefix"),
+            Eof => write!(f, "End of file"),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            LexErrorKind::Eof => write!(f, "{}", self.value),
+            _ => write!(f, "{}: {}", self.loc, self.value),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+pub fn lex(input: &str) -> Result<Vec<Token>, LexError> {
+    let mut tokens = vec![];
+    let input = input.as_bytes(); //バイト列&[u8]を受け取る
+
+    let mut pos = 0;
+    while let Some((tok, p)) = lex_token(input, pos)? {
+        tokens.push(tok);
+        pos = p;
+    }
+    Ok(tokens)
+}
+
+// input が全体の offset バイト目から始まるものとして字句解析する
+// 複数行のスクリプトや複数のファイルを1つの位置空間で扱うときに使う
+pub fn lex_at(input: &str, offset: usize) -> Result<Vec<Token>, LexError> {
+    let shift = |loc: Loc| Loc(loc.0 + offset, loc.1 + offset);
+    match lex(input) {
+        Ok(tokens) => Ok(tokens
+            .into_iter()
+            .map(|tok| Token::new(tok.value, shift(tok.loc)))
+            .collect()),
+        Err(e) => Err(LexError::new(e.value, shift(e.loc))),
+    }
+}
+
+// pos 以降の空白を読み飛ばしてトークンを1つ切り出し、次の位置と一緒に返す。入力の終わりなら None
+// 差分だけを字句解析し直すとき（incremental）にも使う
+pub(crate) fn lex_token(input: &[u8], pos: usize) -> Result<Option<(Token, usize)>, LexError> {
+    let ((), pos) = skip_spaces(input, pos)?;
+    if pos >= input.len() {
+        return Ok(None);
+    }
+
+    let lexed = match input[pos] {
+        b'0'..=b'9' => lex_number(input, pos),
+        b'"' => lex_string(input, pos),
+        b'a'..=b'z' | b'A'..=b'Z' | b'_' => lex_ident(input, pos),
+        //b'+' はバイト文字リテラル、ASCII文字コードのみ対応　b'+' は &[u8; 1]型
+        b'+' => lex_plus(input, pos),
+        b'-' => lex_minus(input, pos),
+        b'*' => lex_asterisk(input, pos),
+        b'/' => lex_slash(input, pos),
+        b'&' => lex_ampersand(input, pos),
+        b'|' => lex_pipe(input, pos),
+        b'^' => lex_caret(input, pos),
+        b'<' => lex_shl(input, pos),
+        b'>' => lex_shr(input, pos),
+        b'~' => lex_tilde(input, pos),
+        b'(' => lex_lparen(input, pos),
+        b')' => lex_rparen(input, pos),
+        b'[' => lex_lbracket(input, pos),
+        b']' => lex_rbracket(input, pos),
+        b',' => lex_comma(input, pos),
+        b';' => lex_semicolon(input, pos),
+        _ => Err(invalid_char_at(input, pos)),
+    }?;
+    Ok(Some(lexed))
+}
+
+// pos から始まる文字と、その次の位置
+// 入力は &str から来ているので、ASCII 以外の文字は UTF-8 の複数バイトをまとめて1文字として扱う
+fn char_at(input: &[u8], pos: usize) -> (char, usize) {
+    let len = match input[pos] {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    };
+    let end = (pos + len).min(input.len());
+    let c = std::str::from_utf8(&input[pos..end])
+        .ok()
+        .and_then(|s| s.chars().next())
+        .unwrap_or(char::REPLACEMENT_CHARACTER);
+    (c, end)
+}
+
+// pos から始まる文字が使えない文字だったときのエラー
+fn invalid_char_at(input: &[u8], pos: usize) -> LexError {
+    let (c, end) = char_at(input, pos);
+    LexError::invalid_char(c, Loc(pos, end))
+}
+
+// pos のバイトが期待するものなら、1バイト（ASCIIだから） consume して pos を1進める
+fn consume_byte(input: &[u8], pos: usize, b: u8) -> Result<(u8, usize), LexError> {
+    if input.len() <= pos {
+        return Err(LexError::eof(Loc(pos, pos)));
+    }
+
+    if input[pos] != b {
+        return Err(invalid_char_at(input, pos));
+    }
+
+    Ok((b, pos + 1))
+}
+
+fn lex_plus(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'+').map(|(_, end)| (Token::plus(Loc(start, end)), end))
+    //okの場合、トークンを作成して返す
+}
+
+fn lex_minus(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'-').map(|(_, end)| (Token::minus(Loc(start, end)), end))
+}
+
+fn lex_asterisk(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'*').map(|(_, end)| (Token::asterisk(Loc(start, end)), end))
+}
+
+fn lex_slash(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'/').map(|(_, end)| (Token::slash(Loc(start, end)), end))
+}
+
+fn lex_ampersand(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'&').map(|(_, end)| (Token::ampersand(Loc(start, end)), end))
+}
+
+fn lex_pipe(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'|').map(|(_, end)| (Token::pipe(Loc(start, end)), end))
+}
+
+fn lex_caret(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'^').map(|(_, end)| (Token::caret(Loc(start, end)), end))
+}
+
+// << と >> は2文字。< や > 単独は使えない文字として扱う
+fn lex_shl(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    let (_, pos) = consume_byte(input, start, b'<')?;
+    consume_byte(input, pos, b'<')
+        .map(|(_, end)| (Token::shl(Loc(start, end)), end))
+        .map_err(|_| invalid_char_at(input, start))
+}
+
+fn lex_shr(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    let (_, pos) = consume_byte(input, start, b'>')?;
+    consume_byte(input, pos, b'>')
+        .map(|(_, end)| (Token::shr(Loc(start, end)), end))
+        .map_err(|_| invalid_char_at(input, start))
+}
+
+fn lex_tilde(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'~').map(|(_, end)| (Token::tilde(Loc(start, end)), end))
+}
+
+fn lex_lparen(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'(').map(|(_, end)| (Token::lparen(Loc(start, end)), end))
+}
+
+fn lex_rparen(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b')').map(|(_, end)| (Token::rparen(Loc(start, end)), end))
+}
+
+fn lex_lbracket(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b'[').map(|(_, end)| (Token::lbracket(Loc(start, end)), end))
+}
+
+fn lex_rbracket(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b']').map(|(_, end)| (Token::rbracket(Loc(start, end)), end))
+}
+
+fn lex_comma(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b',').map(|(_, end)| (Token::comma(Loc(start, end)), end))
+}
+
+fn lex_semicolon(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    consume_byte(input, start, b';').map(|(_, end)| (Token::semicolon(Loc(start, end)), end))
+}
+
+// pos から識別子の文字が続かないか（2i の i が単独の文字かどうか）
+fn is_word_end(input: &[u8], pos: usize) -> bool {
+    pos >= input.len() || !(input[pos].is_ascii_alphanumeric() || input[pos] == b'_')
+}
+
+fn recognize_many(input: &[u8], mut pos: usize, mut f: impl FnMut(u8) -> bool) -> usize {
+    while pos < input.len() && f(input[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+fn lex_number(input: &[u8], pos: usize) -> Result<(Token, usize), LexError> {
+    use std::str::from_utf8;
+
+    // 0x, 0o, 0b で始まれば 16 進・8 進・2 進の整数。桁は _ で区切ってもよい
+    let radix = match input.get(pos..pos + 2) {
+        Some(b"0x") => 16,
+        Some(b"0o") => 8,
+        Some(b"0b") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        // 接頭辞の後に桁がなければエラー（0x を 0 と x に分けない）
+        if !input
+            .get(pos + 2)
+            .is_some_and(|b| (*b as char).is_digit(radix))
+        {
+            return Err(LexError::missing_digits(Loc(pos, pos + 2)));
+        }
+        let end = recognize_many(input, pos + 2, |b| (b as char).is_digit(radix) || b == b'_');
+        let digits: String = from_utf8(&input[pos + 2..end])
+            .unwrap()
+            .chars()
+            .filter(|&c| c != '_')
+            .collect();
+        let n = u64::from_str_radix(&digits, radix)
+            .map_err(|_| LexError::number_overflow(Loc(pos, end)))?;
+        return Ok((Token::number(n, Loc(pos, end)), end));
+    }
+
+    let start = pos;
+    let end = recognize_many(input, pos, |b| b"0123456789".contains(&b));
+
+    // "." の後に数字が続けば小数
+    let (end, is_float) =
+        if end + 1 < input.len() && input[end] == b'.' && input[end + 1].is_ascii_digit() {
+            (recognize_many(input, end + 1, |b| b.is_ascii_digit()), true)
+        } else {
+            (end, false)
+        };
+
+    // e の後に（符号つきの）数字が続けば指数表記の小数（1e3, 1.5e-3）
+    let exp_digits = match input.get(end + 1) {
+        Some(b'+') | Some(b'-') => end + 2,
+        _ => end + 1,
+    };
+    let (end, is_float) = if matches!(input.get(end), Some(b'e') | Some(b'E'))
+        && input.get(exp_digits).is_some_and(|b| b.is_ascii_digit())
+    {
+        (
+            recognize_many(input, exp_digits, |b| b.is_ascii_digit()),
+            true,
+        )
+    } else {
+        (end, is_float)
+    };
+
+    // 直後に i が続けば虚数
+    if end < input.len() && input[end] == b'i' && is_word_end(input, end + 1) {
+        let x = from_utf8(&input[start..end])
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+        return Ok((Token::imaginary(x, Loc(start, end + 1)), end + 1));
+    }
+    if is_float {
+        let x = from_utf8(&input[start..end])
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+        return Ok((Token::float(x, Loc(start, end)), end));
+    }
+
+    let n = from_utf8(&input[start..end])
+        .unwrap()
+        .parse::<u64>()
+        .map_err(|_| LexError::number_overflow(Loc(start, end)))?;
+
+    Ok((Token::number(n, Loc(start, end)), end))
+}
+
+// 識別子にならない名前。in はキーワード、i は虚数単位として読む
+pub fn is_reserved(name: &str) -> bool {
+    matches!(name, "in" | "i")
+}
+
+// 識別子（変数名・関数名・単位名）。英字か _ で始まり、英数字か _ が続く
+fn lex_ident(input: &[u8], pos: usize) -> Result<(Token, usize), LexError> {
+    use std::str::from_utf8;
+
+    let start = pos;
+    let end = recognize_many(input, pos, |b| b.is_ascii_alphanumeric() || b == b'_');
+    let s = from_utf8(&input[start..end]).unwrap();
+
+    let tok = match s {
+        "in" => Token::kw_in(Loc(start, end)),
+        "i" => Token::imaginary(1.0, Loc(start, end)),
+        _ => Token::ident(s, Loc(start, end)),
+    };
+    Ok((tok, end))
+}
+
+// 文字列リテラル。位置は両端の " を含む
+// 使えるエスケープは \" \\ \n \t \r
+fn lex_string(input: &[u8], start: usize) -> Result<(Token, usize), LexError> {
+    let mut bytes = vec![];
+    let mut pos = start + 1;
+    loop {
+        match input.get(pos) {
+            None => return Err(LexError::unterminated_string(Loc(start, pos))),
+            Some(b'"') => break,
+            Some(b'\\') => {
+                let b = match input.get(pos + 1) {
+                    Some(b'"') => b'"',
+                    Some(b'\\') => b'\\',
+                    Some(b'n') => b'\n',
+                    Some(b't') => b'\t',
+                    Some(b'r') => b'\r',
+                    Some(_) => {
+                        let (c, end) = char_at(input, pos + 1);
+                        return Err(LexError::invalid_escape(c, Loc(pos, end)));
+                    }
+                    None => return Err(LexError::unterminated_string(Loc(start, pos + 1))),
+                };
+                bytes.push(b);
+                pos += 2;
+            }
+            Some(&b) => {
+                bytes.push(b);
+                pos += 1;
+            }
+        }
+    }
+    // 入力は &str で、エスケープで入れるのは ASCII だけなので UTF-8 として正しい
+    let s = String::from_utf8(bytes).unwrap();
+    Ok((Token::string(s, Loc(start, pos + 1)), pos + 1))
+}
+
+fn skip_spaces(input: &[u8], pos: usize) -> Result<((), usize), LexError> {
+    let end = recognize_many(input, pos, |b| b" \r\n\t".contains(&b));
+    Ok(((), end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lexer() {
+        assert_eq!(
+            lex("10 + f(x, 2)").unwrap(),
+            vec![
+                Token::number(10, Loc(0, 2)),
+                Token::plus(Loc(3, 4)),
+                Token::ident("f", Loc(5, 6)),
+                Token::lparen(Loc(6, 7)),
+                Token::ident("x", Loc(7, 8)),
+                Token::comma(Loc(8, 9)),
+                Token::number(2, Loc(10, 11)),
+                Token::rparen(Loc(11, 12)),
+            ]
+        );
+        assert_eq!(
+            lex("2.5 km in m").unwrap(),
+            vec![
+                Token::float(2.5, Loc(0, 3)),
+                Token::ident("km", Loc(4, 6)),
+                Token::kw_in(Loc(7, 9)),
+                Token::ident("m", Loc(10, 11)),
+            ]
+        );
+        assert_eq!(
+            lex("1e3 1.5E-3 2e+2i 2 e3").unwrap(),
+            vec![
+                Token::float(1e3, Loc(0, 3)),
+                Token::float(1.5e-3, Loc(4, 10)),
+                Token::imaginary(2e2, Loc(11, 16)),
+                Token::number(2, Loc(17, 18)),
+                Token::ident("e3", Loc(19, 21)),
+            ]
+        );
+        assert_eq!(
+            lex("[1, 2.5i; i, 3]").unwrap(),
+            vec![
+                Token::lbracket(Loc(0, 1)),
+                Token::number(1, Loc(1, 2)),
+                Token::comma(Loc(2, 3)),
+                Token::imaginary(2.5, Loc(4, 8)),
+                Token::semicolon(Loc(8, 9)),
+                Token::imaginary(1.0, Loc(10, 11)),
+                Token::comma(Loc(11, 12)),
+                Token::number(3, Loc(13, 14)),
+                Token::rbracket(Loc(14, 15)),
+            ]
+        );
+        assert_eq!(lex("1 $ 2"), Err(LexError::invalid_char('$', Loc(2, 3))));
+        assert_eq!(lex("1 é"), Err(LexError::invalid_char('é', Loc(2, 4))));
+        assert_eq!(
+            lex_at("1 + x", 10),
+            Ok(vec![
+                Token::number(1, Loc(10, 11)),
+                Token::plus(Loc(12, 13)),
+                Token::ident("x", Loc(14, 15)),
+            ])
+        );
+        assert_eq!(lex_at("$", 3), Err(LexError::invalid_char('$', Loc(3, 4))));
+        assert_eq!(
+            lex(r#"len("a\"b\n") + "é""#).unwrap(),
+            vec![
+                Token::ident("len", Loc(0, 3)),
+                Token::lparen(Loc(3, 4)),
+                Token::string("a\"b\n", Loc(4, 12)),
+                Token::rparen(Loc(12, 13)),
+                Token::plus(Loc(14, 15)),
+                Token::string("é", Loc(16, 20)),
+            ]
+        );
+        assert_eq!(
+            lex("1 + \"abc"),
+            Err(LexError::unterminated_string(Loc(4, 8)))
+        );
+        assert_eq!(
+            lex(r#""a\qb""#),
+            Err(LexError::invalid_escape('q', Loc(2, 4)))
+        );
+        assert_eq!(
+            lex("0xff 0o17 0b1010").unwrap(),
+            vec![
+                Token::number(255, Loc(0, 4)),
+                Token::number(15, Loc(5, 9)),
+                Token::number(10, Loc(10, 16)),
+            ]
+        );
+        assert_eq!(lex("1 + 0x"), Err(LexError::missing_digits(Loc(4, 6))));
+        assert_eq!(lex("0b2"), Err(LexError::missing_digits(Loc(0, 2))));
+        assert_eq!(
+            lex("0x1_0000_0000_0000_0000"),
+            Err(LexError::number_overflow(Loc(0, 23)))
+        );
+        assert_eq!(
+            lex("~a&b|c^1<<2>>3").unwrap(),
+            vec![
+                Token::tilde(Loc(0, 1)),
+                Token::ident("a", Loc(1, 2)),
+                Token::ampersand(Loc(2, 3)),
+                Token::ident("b", Loc(3, 4)),
+                Token::pipe(Loc(4, 5)),
+                Token::ident("c", Loc(5, 6)),
+                Token::caret(Loc(6, 7)),
+                Token::number(1, Loc(7, 8)),
+                Token::shl(Loc(8, 10)),
+                Token::number(2, Loc(10, 11)),
+                Token::shr(Loc(11, 13)),
+                Token::number(3, Loc(13, 14)),
+            ]
+        );
+        assert_eq!(lex("1 < 2"), Err(LexError::invalid_char('<', Loc(2, 3))));
+        assert_eq!(
+            lex("99999999999999999999"),
+            Err(LexError::number_overflow(Loc(0, 20)))
+        );
+    }
+}
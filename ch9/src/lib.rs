@@ -0,0 +1,282 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+pub mod asm;
+pub mod complex;
+pub mod explain;
+pub mod format;
+pub mod host;
+pub mod incremental;
+pub mod int;
+pub mod interpreter;
+pub mod json;
+pub mod lexer;
+pub mod lsp;
+pub mod matrix;
+pub mod parser;
+pub mod serialize;
+pub mod sexpr;
+pub mod source_map;
+pub mod units;
+pub mod wasm;
+
+pub use asm::{CodegenError, CodegenErrorKind};
+pub use complex::Complex;
+pub use explain::{Explanation, Step};
+pub use format::{Formatter, Notation};
+pub use host::{HostFn, Variadic};
+pub use incremental::{relex, reparse, Relexed, TextEdit};
+pub use int::{FixedInt, IntMode, IntWidth, OverflowPolicy, Radix};
+pub use interpreter::{Interpreter, InterpreterError, InterpreterErrorKind, Value};
+pub use lexer::{lex, lex_at, LexError, LexErrorKind, Token, TokenKind};
+pub use matrix::Matrix;
+pub use parser::{parse, Ast, AstKind, BinOp, BinOpKind, ParseError, UniOp, UniOpKind};
+pub use serialize::{DecodeError, Serialize, Tagged};
+pub use sexpr::{Sexpr, SexprError};
+pub use source_map::{FileId, Location, SourceMap};
+pub use units::{Dim, Quantity, Unit};
+
+// 位置情報（Loc(4, 8) なら 入力文字の5文字目から9文字目までの範囲を表す）
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Loc(pub usize, pub usize);
+impl Loc {
+    pub fn merge(&self, other: &Loc) -> Loc {
+        use std::cmp::{max, min};
+        Loc(min(self.0, other.0), max(self.1, other.1))
+    }
+}
+
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.0, self.1)
+    }
+}
+
+// アノテーション。値に位置情報を持たせる
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Annot<T> {
+    pub value: T,
+    pub loc: Loc,
+}
+impl<T> Annot<T> {
+    pub fn new(value: T, loc: Loc) -> Self {
+        Annot { value, loc }
+    }
+}
+
+// LexError, ParseError, InterpreterError の列挙型を作成
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Lexer(LexError),
+    Parser(ParseError),
+    Interpreter(InterpreterError),
+    Codegen(CodegenError),
+}
+
+// Error::from で LexError, ParseError を透過的に扱う
+// この from は Error 型を返す関数の中で、Result / Option を ? で評価したとき、その結果が LexError 型だった場合に自動的に呼び出される
+impl From<LexError> for Error {
+    fn from(e: LexError) -> Self {
+        Error::Lexer(e)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::Parser(e)
+    }
+}
+
+impl From<InterpreterError> for Error {
+    fn from(e: InterpreterError) -> Self {
+        Error::Interpreter(e)
+    }
+}
+
+impl From<CodegenError> for Error {
+    fn from(e: CodegenError) -> Self {
+        Error::Codegen(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Lexer(e) => write!(f, "lexer error: {}", e),
+            Error::Parser(e) => write!(f, "parser error: {}", e),
+            Error::Interpreter(e) => write!(f, "interpreter error: {}", e),
+            Error::Codegen(e) => write!(f, "codegen error: {}", e),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Lexer(e) => Some(e),
+            Error::Parser(e) => Some(e),
+            Error::Interpreter(e) => Some(e),
+            Error::Codegen(e) => Some(e),
+        }
+    }
+}
+
+impl Error {
+    // エラーの位置情報。入力の末尾で起きたエラーは None
+    pub fn loc(&self) -> Option<&Loc> {
+        match self {
+            Error::Lexer(e) => Some(&e.loc),
+            Error::Parser(e) => e.loc(),
+            Error::Interpreter(e) => Some(&e.loc),
+            Error::Codegen(e) => Some(&e.loc),
+        }
+    }
+
+    // 位置を含まないエラーの説明。位置は呼び出し側が行と列で示す
+    pub fn kind_message(&self) -> String {
+        match self {
+            Error::Lexer(e) => format!("lexer error: {}", e.value),
+            Error::Parser(e) => format!("parser error: {}", e.message()),
+            Error::Interpreter(e) => format!("interpreter error: {}", e.value),
+            Error::Codegen(e) => format!("codegen error: {}", e.value),
+        }
+    }
+
+    // 入力行の下にエラー箇所を ^^^ で示した文字列を返す
+    pub fn show_diagnostic(&self, input: &str) -> String {
+        let loc = match self.loc() {
+            Some(loc) => loc.clone(),
+            None => Loc(input.len(), input.len() + 1),
+        };
+        format!(
+            "{}\n{}{}\n",
+            input,
+            " ".repeat(loc.0),
+            "^".repeat(loc.1 - loc.0)
+        )
+    }
+}
+
+// SourceMap に登録したソースの一部を字句解析・構文解析する
+pub fn parse_span(map: &SourceMap, span: &Loc) -> Result<Ast, Error> {
+    let tokens = lex_at(map.snippet(span), span.0)?;
+    Ok(parse(tokens)?)
+}
+
+// 外部から使うための窓口。字句解析・構文解析・評価をまとめて行う
+#[derive(Default)]
+pub struct Engine {
+    interp: Interpreter,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            interp: Interpreter::new(),
+        }
+    }
+
+    pub fn eval(&mut self, input: &str) -> Result<Value, Error> {
+        let tokens = lex(input)?;
+        let ast = parse(tokens)?;
+        Ok(self.interp.eval(&ast)?)
+    }
+
+    // SourceMap に登録したソースの一部を評価する。エラーの位置は SourceMap で解決できる
+    pub fn eval_span(&mut self, map: &SourceMap, span: &Loc) -> Result<Value, Error> {
+        let ast = parse_span(map, span)?;
+        Ok(self.interp.eval(&ast)?)
+    }
+
+    // 式を1段ずつ評価して、各段階の式を返す。評価中のエラーは Explanation の result に入る
+    pub fn explain(&mut self, input: &str) -> Result<Explanation, Error> {
+        let tokens = lex(input)?;
+        let ast = parse(tokens)?;
+        Ok(self.interp.explain(&ast))
+    }
+
+    pub fn explain_span(&mut self, map: &SourceMap, span: &Loc) -> Result<Explanation, Error> {
+        let ast = parse_span(map, span)?;
+        Ok(self.interp.explain(&ast))
+    }
+
+    // in と i は予約語なので ReservedName になる
+    pub fn set_var(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Result<(), InterpreterErrorKind> {
+        self.interp.set_var(name, value)
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&Value> {
+        self.interp.get_var(name)
+    }
+
+    pub fn int_mode(&self) -> IntMode {
+        self.interp.int_mode()
+    }
+
+    pub fn set_int_mode(&mut self, mode: IntMode) {
+        self.interp.set_int_mode(mode);
+    }
+
+    // f64 の1引数・2引数の関数、または &[Value] を受け取る関数を登録できる
+    pub fn register_fn<Args, F>(&mut self, name: impl Into<String>, f: F)
+    where
+        F: HostFn<Args> + 'static,
+    {
+        self.interp.register_fn(name, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engine() {
+        let mut engine = Engine::new();
+        engine.set_var("x", 10).unwrap();
+        engine.set_var("half", 0.5).unwrap();
+        engine.register_fn("max", |args: &[Value]| {
+            args.iter()
+                .cloned()
+                .reduce(|a, b| if a.as_f64() >= b.as_f64() { a } else { b })
+                .ok_or_else(|| "max needs at least one argument".to_string())
+        });
+
+        assert_eq!(engine.eval("x * 2 + 1"), Ok(Value::Int(21)));
+        assert_eq!(engine.eval("x * half"), Ok(Value::Float(5.0)));
+        assert_eq!(engine.eval("max(1, x, 3)"), Ok(Value::Int(10)));
+        assert_eq!(
+            engine.eval("2 * max()"),
+            Err(Error::Interpreter(InterpreterError::new(
+                InterpreterErrorKind::HostError("max needs at least one argument".to_string()),
+                Loc(4, 9)
+            )))
+        );
+        assert_eq!(
+            engine.eval("min(1)"),
+            Err(Error::Interpreter(InterpreterError::new(
+                InterpreterErrorKind::UndefinedFunction("min".to_string()),
+                Loc(0, 6)
+            )))
+        );
+        // i は虚数単位、in はキーワードとして読むので変数にできない
+        assert_eq!(
+            engine.set_var("i", 1),
+            Err(InterpreterErrorKind::ReservedName("i".to_string()))
+        );
+        assert_eq!(engine.get_var("i"), None);
+    }
+
+    #[test]
+    fn test_show_diagnostic() {
+        let mut engine = Engine::new();
+        let err = engine.eval("1 + y").unwrap_err();
+        assert_eq!(err.show_diagnostic("1 + y"), "1 + y\n    ^\n");
+        let err = engine.eval("1 +").unwrap_err();
+        assert_eq!(err.show_diagnostic("1 +"), "1 +\n   ^\n");
+    }
+}
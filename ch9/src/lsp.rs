@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::json::Json;
+use crate::lexer::{is_reserved, lex_at};
+use crate::parser::{parse, Ast, AstKind};
+use crate::{Annot, Error, Interpreter, Loc};
+
+// ch9 の式を並べたファイル。1行に1つの式を書き、"name = 式" の行で変数を定義する
+// 位置情報はすべてファイル先頭からのバイト位置
+#[derive(Debug)]
+pub struct Document {
+    text: String,
+    // 各行の先頭のバイト位置
+    line_starts: Vec<usize>,
+    statements: Vec<Statement>,
+}
+
+#[derive(Debug)]
+struct Statement {
+    line: usize,
+    // 定義している変数名（"x = 1 + 2" の x）
+    name: Option<Annot<String>>,
+    ast: Result<Ast, Error>,
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !is_reserved(s)
+}
+
+// offset バイト目から始まる式を解析する。位置情報はファイル全体での位置になる
+fn parse_at(src: &str, offset: usize) -> Result<Ast, Error> {
+    Ok(parse(lex_at(src, offset)?)?)
+}
+
+impl Document {
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+
+        let mut statements = vec![];
+        for (line, &start) in line_starts.iter().enumerate() {
+            let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+            let src = &text[start..end];
+            if src.trim().is_empty() {
+                continue;
+            }
+            let (name, offset) = match src.split_once('=') {
+                Some((lhs, _)) if is_ident(lhs.trim()) => {
+                    let name_start = start + lhs.len() - lhs.trim_start().len();
+                    let name = lhs.trim().to_string();
+                    let loc = Loc(name_start, name_start + name.len());
+                    (Some(Annot::new(name, loc)), start + lhs.len() + 1)
+                }
+                _ => (None, start),
+            };
+            statements.push(Statement {
+                line,
+                name,
+                ast: parse_at(&text[offset..end], offset),
+            });
+        }
+
+        Document {
+            text,
+            line_starts,
+            statements,
+        }
+    }
+
+    // バイト位置を LSP の位置（行と UTF-16 での列）にする
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&s| s <= offset) - 1;
+        let start = self.line_starts[line];
+        (line, self.text[start..offset].encode_utf16().count())
+    }
+
+    // LSP の位置をバイト位置にする。行末を越えた列は行末に丸める
+    pub fn offset(&self, line: usize, character: usize) -> usize {
+        let Some(&start) = self.line_starts.get(line) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    fn line_end(&self, line: usize) -> usize {
+        let start = self.line_starts[line];
+        self.text[start..]
+            .find('\n')
+            .map_or(self.text.len(), |i| start + i)
+    }
+
+    // 字句解析・構文解析のエラーを (範囲, メッセージ) で返す
+    pub fn diagnostics(&self) -> Vec<(Loc, String)> {
+        self.statements
+            .iter()
+            .filter_map(|st| {
+                let e = st.ast.as_ref().err()?;
+                // 入力の終わりで起きたエラーは行末を指す
+                let loc = e.loc().cloned().unwrap_or_else(|| {
+                    let end = self.line_end(st.line);
+                    Loc(end, end)
+                });
+                Some((loc, e.kind_message()))
+            })
+            .collect()
+    }
+
+    fn statement_at(&self, offset: usize) -> Option<&Statement> {
+        let (line, _) = self.position(offset);
+        self.statements.iter().find(|st| st.line == line)
+    }
+
+    // 変数の定義を上から順に評価した環境。before 行より前の定義だけを使う
+    fn env(&self, before: usize) -> Interpreter {
+        let mut interp = Interpreter::new();
+        for st in self.statements.iter().take_while(|st| st.line < before) {
+            if let (Some(name), Ok(ast)) = (&st.name, &st.ast) {
+                if let Ok(v) = interp.eval(ast) {
+                    // 予約語は定義として読まないので、ここでは失敗しない
+                    let _ = interp.set_var(name.value.clone(), v);
+                }
+            }
+        }
+        interp
+    }
+
+    // offset にある部分式を評価して、その範囲と "値: 型" を返す
+    // 評価できなければ理由を返す
+    pub fn hover(&self, offset: usize) -> Option<(Loc, String)> {
+        let st = self.statement_at(offset)?;
+        let mut interp = self.env(st.line);
+        let (loc, result) = match &st.name {
+            Some(name) if name.loc.0 <= offset && offset < name.loc.1 => {
+                let ast = st.ast.as_ref().ok()?;
+                (name.loc.clone(), interp.eval(ast))
+            }
+            _ => {
+                let node = node_at(st.ast.as_ref().ok()?, offset)?;
+                (node.loc.clone(), interp.eval(node))
+            }
+        };
+        let text = match result {
+            Ok(v) => format!("{}: {}", v, v.type_name()),
+            Err(e) => format!("cannot evaluate: {}", e),
+        };
+        Some((loc, text))
+    }
+
+    // offset にある変数を定義している名前の範囲。手前の行の定義を優先し、なければ後ろの行から探す
+    pub fn definition(&self, offset: usize) -> Option<Loc> {
+        let st = self.statement_at(offset)?;
+        let node = node_at(st.ast.as_ref().ok()?, offset)?;
+        let AstKind::Var(var) = &node.value else {
+            return None;
+        };
+        let defines = |s: &&Statement| s.name.as_ref().is_some_and(|n| &n.value == var);
+        let before = self
+            .statements
+            .iter()
+            .filter(|s| s.line < st.line)
+            .rfind(defines);
+        let after = || {
+            self.statements
+                .iter()
+                .filter(|s| s.line > st.line)
+                .find(defines)
+        };
+        let def = before.or_else(after)?;
+        def.name.as_ref().map(|n| n.loc.clone())
+    }
+}
+
+// offset を含む一番深いノード
+fn node_at(ast: &Ast, offset: usize) -> Option<&Ast> {
+    if !(ast.loc.0 <= offset && offset < ast.loc.1) {
+        return None;
+    }
+    ast.children()
+        .into_iter()
+        .find_map(|c| node_at(c, offset))
+        .or(Some(ast))
+}
+
+// LSP のサーバー。開いている文書を URI ごとに持つ
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    exit: bool,
+}
+
+fn range(doc: &Document, loc: &Loc) -> Json {
+    let pos = |offset| {
+        let (line, character) = doc.position(offset);
+        Json::object(vec![("line", line.into()), ("character", character.into())])
+    };
+    Json::object(vec![("start", pos(loc.0)), ("end", pos(loc.1))])
+}
+
+fn response(id: &Json, result: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        ("result", result),
+    ])
+}
+
+fn error_response(id: &Json, code: i64, message: &str) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        (
+            "error",
+            Json::object(vec![("code", code.into()), ("message", message.into())]),
+        ),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+// JSON-RPC のエラーコード
+const PARSE_ERROR: i64 = -32700;
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+impl Server {
+    pub fn new() -> Self {
+        Server::default()
+    }
+
+    // exit 通知を受け取ったら true
+    pub fn exited(&self) -> bool {
+        self.exit
+    }
+
+    // メッセージを1つ処理し、クライアントに送るメッセージを返す
+    pub fn handle(&mut self, msg: &Json) -> Vec<Json> {
+        let method = msg.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = msg.get("params").unwrap_or(&Json::Null);
+        let Some(id) = msg.get("id") else {
+            return self.handle_notification(method, params);
+        };
+        let result = match method {
+            "initialize" => Some(Json::object(vec![(
+                "capabilities",
+                Json::object(vec![
+                    // 変更のたびに文書全体を送ってもらう
+                    ("textDocumentSync", 1.0.into()),
+                    ("hoverProvider", true.into()),
+                    ("definitionProvider", true.into()),
+                ]),
+            )])),
+            "shutdown" => Some(Json::Null),
+            "textDocument/hover" => self.position_params(params).map(|(_, doc, offset)| {
+                doc.hover(offset).map_or(Json::Null, |(loc, text)| {
+                    Json::object(vec![
+                        (
+                            "contents",
+                            Json::object(vec![
+                                ("kind", "plaintext".into()),
+                                ("value", text.into()),
+                            ]),
+                        ),
+                        ("range", range(doc, &loc)),
+                    ])
+                })
+            }),
+            "textDocument/definition" => self.position_params(params).map(|(uri, doc, offset)| {
+                doc.definition(offset).map_or(Json::Null, |loc| {
+                    Json::object(vec![("uri", uri.into()), ("range", range(doc, &loc))])
+                })
+            }),
+            _ => return vec![error_response(id, METHOD_NOT_FOUND, "method not found")],
+        };
+        match result {
+            Some(result) => vec![response(id, result)],
+            None => vec![error_response(id, INVALID_PARAMS, "invalid params")],
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params.path("textDocument.uri").and_then(Json::as_str);
+        let text = match method {
+            "exit" => {
+                self.exit = true;
+                return vec![];
+            }
+            "textDocument/didOpen" => params.path("textDocument.text").and_then(Json::as_str),
+            // 全体を送ってもらう設定なので、最後の変更に文書全体が入っている
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .and_then(Json::as_array)
+                .and_then(|changes| changes.last())
+                .and_then(|c| c.get("text"))
+                .and_then(Json::as_str),
+            "textDocument/didClose" => {
+                if let Some(uri) = uri {
+                    self.documents.remove(uri);
+                }
+                return vec![];
+            }
+            _ => return vec![],
+        };
+        let (Some(uri), Some(text)) = (uri, text) else {
+            return vec![];
+        };
+        let doc = Document::new(text);
+        let diagnostics = doc
+            .diagnostics()
+            .iter()
+            .map(|(loc, message)| {
+                Json::object(vec![
+                    ("range", range(&doc, loc)),
+                    ("severity", 1.0.into()),
+                    ("source", "ch9".into()),
+                    ("message", message.as_str().into()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        self.documents.insert(uri.to_string(), doc);
+        vec![notification(
+            "textDocument/publishDiagnostics",
+            Json::object(vec![
+                ("uri", uri.into()),
+                ("diagnostics", diagnostics.into()),
+            ]),
+        )]
+    }
+
+    // textDocument と position を持つパラメータから、文書とバイト位置を取り出す
+    fn position_params<'a>(&'a self, params: &'a Json) -> Option<(&'a str, &'a Document, usize)> {
+        let uri = params.path("textDocument.uri").and_then(Json::as_str)?;
+        let line = params.path("position.line").and_then(Json::as_u64)?;
+        let character = params.path("position.character").and_then(Json::as_u64)?;
+        let doc = self.documents.get(uri)?;
+        Some((uri, doc, doc.offset(line as usize, character as usize)))
+    }
+}
+
+// Content-Length ヘッダーつきのメッセージを1つ読む。入力の終わりなら None
+pub fn read_message(r: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if r.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(v) = header.strip_prefix("Content-Length:") {
+            len = v.trim().parse::<usize>().ok();
+        }
+    }
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no Content-Length"))?;
+    let mut body = vec![0; len];
+    r.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(w: &mut impl Write, msg: &Json) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    w.flush()
+}
+
+// 入力が終わるか exit 通知を受け取るまでメッセージを処理し続ける
+pub fn run(mut r: impl BufRead, mut w: impl Write) -> io::Result<()> {
+    let mut server = Server::new();
+    while let Some(body) = read_message(&mut r)? {
+        let replies = match Json::parse(&body) {
+            Ok(msg) => server.handle(&msg),
+            Err(e) => vec![error_response(&Json::Null, PARSE_ERROR, &e.to_string())],
+        };
+        for reply in &replies {
+            write_message(&mut w, reply)?;
+        }
+        if server.exited() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // クライアントから送るメッセージを Content-Length つきで並べる
+    fn script(messages: &[&str]) -> Vec<u8> {
+        messages
+            .iter()
+            .flat_map(|m| format!("Content-Length: {}\r\n\r\n{}", m.len(), m).into_bytes())
+            .collect()
+    }
+
+    fn replies(input: &[u8]) -> Vec<Json> {
+        let mut out = vec![];
+        run(input, &mut out).unwrap();
+        let mut r = &out[..];
+        let mut replies = vec![];
+        while let Some(body) = read_message(&mut r).unwrap() {
+            replies.push(Json::parse(&body).unwrap());
+        }
+        replies
+    }
+
+    #[test]
+    fn test_document() {
+        let doc = Document::new("x = 1 + 2\ny = x * 3\n\nx + é + y\nz = 1 +");
+        assert_eq!(doc.position(12), (1, 2));
+        assert_eq!(doc.offset(1, 2), 12);
+        // é は UTF-8 で2バイト、UTF-16 で1単位
+        assert_eq!(doc.offset(3, 5), 27);
+        assert_eq!(doc.position(27), (3, 5));
+
+        assert_eq!(
+            doc.diagnostics(),
+            vec![
+                (Loc(25, 27), "lexer error: invalid char 'é'".to_string()),
+                (Loc(39, 39), "parser error: End of file".to_string()),
+            ]
+        );
+        assert_eq!(doc.hover(14), Some((Loc(14, 15), "3: int".to_string())));
+        assert_eq!(doc.hover(16), Some((Loc(14, 19), "9: int".to_string())));
+        assert_eq!(doc.hover(10), Some((Loc(10, 11), "9: int".to_string())));
+        assert_eq!(doc.definition(14), Some(Loc(0, 1)));
+        assert_eq!(doc.definition(16), None);
+
+        // i は予約語なので定義にならず、式として読んでエラーになる
+        let doc = Document::new("i = 3\n2 * i");
+        assert_eq!(
+            doc.diagnostics(),
+            vec![(Loc(2, 3), "lexer error: invalid char '='".to_string())]
+        );
+        assert_eq!(doc.definition(10), None);
+    }
+
+    #[test]
+    fn test_server() {
+        let input = script(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.calc","languageId":"ch9","version":1,"text":"r = 2 m\narea = r * r * 3\n(1 + 2"}}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///a.calc"},"position":{"line":1,"character":7}}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/definition","params":{"textDocument":{"uri":"file:///a.calc"},"position":{"line":1,"character":11}}}"#,
+            r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.calc","version":2},"contentChanges":[{"text":"1 + 2"}]}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"unknown/method","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"shutdown"}"#,
+        ]);
+        let replies: Vec<String> = replies(&input).iter().map(Json::to_string).collect();
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"textDocumentSync":1,"hoverProvider":true,"definitionProvider":true}}}"#,
+                r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///a.calc","diagnostics":[{"range":{"start":{"line":2,"character":0},"end":{"line":2,"character":1}},"severity":1,"source":"ch9","message":"parser error: '(' is not closed"}]}}"#,
+                r#"{"jsonrpc":"2.0","id":2,"result":{"contents":{"kind":"plaintext","value":"2 m: quantity"},"range":{"start":{"line":1,"character":7},"end":{"line":1,"character":8}}}}"#,
+                r#"{"jsonrpc":"2.0","id":3,"result":{"uri":"file:///a.calc","range":{"start":{"line":0,"character":0},"end":{"line":0,"character":1}}}}"#,
+                r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///a.calc","diagnostics":[]}}"#,
+                r#"{"jsonrpc":"2.0","id":4,"error":{"code":-32601,"message":"method not found"}}"#,
+                r#"{"jsonrpc":"2.0","id":5,"result":null}"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+
+use crate::interpreter::InterpreterErrorKind;
+
+// 実数の行列。要素は行優先で data に並べる
+// ベクトルは 1 行（[1, 2, 3]）または 1 列（[1; 2; 3]）の行列として扱う
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+// 特異行列とみなすピボットの大きさ
+const EPSILON: f64 = 1e-12;
+
+impl Matrix {
+    pub fn new(rows: usize, cols: usize, data: Vec<f64>) -> Self {
+        assert_eq!(rows * cols, data.len());
+        Matrix { rows, cols, data }
+    }
+
+    pub fn identity(n: usize) -> Self {
+        let mut m = Matrix::new(n, n, vec![0.0; n * n]);
+        for i in 0..n {
+            m[(i, i)] = 1.0;
+        }
+        m
+    }
+
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    // 各行の要素を上から順に返す
+    pub fn iter_rows(&self) -> impl Iterator<Item = &[f64]> {
+        self.data.chunks(self.cols.max(1))
+    }
+
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Matrix {
+        Matrix::new(
+            self.rows,
+            self.cols,
+            self.data.iter().map(|&x| f(x)).collect(),
+        )
+    }
+
+    // 要素ごとの演算。形が違えば ShapeMismatch
+    pub fn zip_with(
+        &self,
+        other: &Matrix,
+        f: impl Fn(f64, f64) -> f64,
+    ) -> Result<Matrix, InterpreterErrorKind> {
+        if self.shape() != other.shape() {
+            return Err(self.shape_mismatch(other));
+        }
+        let data = self
+            .data
+            .iter()
+            .zip(other.data.iter())
+            .map(|(&a, &b)| f(a, b))
+            .collect();
+        Ok(Matrix::new(self.rows, self.cols, data))
+    }
+
+    // 行列積。左の列数と右の行数が一致している必要がある
+    pub fn matmul(&self, other: &Matrix) -> Result<Matrix, InterpreterErrorKind> {
+        if self.cols != other.rows {
+            return Err(self.shape_mismatch(other));
+        }
+        let mut m = Matrix::new(self.rows, other.cols, vec![0.0; self.rows * other.cols]);
+        for i in 0..self.rows {
+            for j in 0..other.cols {
+                m[(i, j)] = (0..self.cols).map(|k| self[(i, k)] * other[(k, j)]).sum();
+            }
+        }
+        Ok(m)
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut m = Matrix::new(self.cols, self.rows, vec![0.0; self.data.len()]);
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                m[(j, i)] = self[(i, j)];
+            }
+        }
+        m
+    }
+
+    // 行列式。部分ピボット選択つきのガウスの消去法で上三角にして対角成分を掛ける
+    pub fn det(&self) -> Result<f64, InterpreterErrorKind> {
+        self.check_square()?;
+        let n = self.rows;
+        let mut a = self.clone();
+        let mut det = 1.0;
+        for k in 0..n {
+            let p = a.pivot_row(k);
+            if a[(p, k)] == 0.0 {
+                return Ok(0.0);
+            }
+            if p != k {
+                a.swap_rows(p, k);
+                det = -det;
+            }
+            det *= a[(k, k)];
+            for i in k + 1..n {
+                let r = a[(i, k)] / a[(k, k)];
+                for j in k..n {
+                    a[(i, j)] -= r * a[(k, j)];
+                }
+            }
+        }
+        Ok(det)
+    }
+
+    // 逆行列。ガウス・ジョルダン法で [A | I] を [I | A^-1] にする
+    pub fn inverse(&self) -> Result<Matrix, InterpreterErrorKind> {
+        self.check_square()?;
+        let n = self.rows;
+        let mut a = self.clone();
+        let mut inv = Matrix::identity(n);
+        for k in 0..n {
+            let p = a.pivot_row(k);
+            if a[(p, k)].abs() < EPSILON {
+                return Err(InterpreterErrorKind::SingularMatrix);
+            }
+            a.swap_rows(p, k);
+            inv.swap_rows(p, k);
+            let d = a[(k, k)];
+            for j in 0..n {
+                a[(k, j)] /= d;
+                inv[(k, j)] /= d;
+            }
+            for i in (0..n).filter(|&i| i != k) {
+                let r = a[(i, k)];
+                for j in 0..n {
+                    a[(i, j)] -= r * a[(k, j)];
+                    inv[(i, j)] -= r * inv[(k, j)];
+                }
+            }
+        }
+        Ok(inv)
+    }
+
+    fn check_square(&self) -> Result<(), InterpreterErrorKind> {
+        if self.rows == self.cols {
+            Ok(())
+        } else {
+            Err(InterpreterErrorKind::NotSquare(self.shape()))
+        }
+    }
+
+    // k 列目で k 行目以降の絶対値が最大の行
+    fn pivot_row(&self, k: usize) -> usize {
+        (k..self.rows)
+            .max_by(|&i, &j| self[(i, k)].abs().total_cmp(&self[(j, k)].abs()))
+            .unwrap()
+    }
+
+    fn swap_rows(&mut self, i: usize, j: usize) {
+        for c in 0..self.cols {
+            self.data.swap(i * self.cols + c, j * self.cols + c);
+        }
+    }
+
+    fn shape_mismatch(&self, other: &Matrix) -> InterpreterErrorKind {
+        InterpreterErrorKind::ShapeMismatch {
+            left: self.shape(),
+            right: other.shape(),
+        }
+    }
+}
+
+// m[(i, j)] で i 行 j 列の要素にアクセスする
+impl std::ops::Index<(usize, usize)> for Matrix {
+    type Output = f64;
+    fn index(&self, (i, j): (usize, usize)) -> &f64 {
+        &self.data[i * self.cols + j]
+    }
+}
+
+impl std::ops::IndexMut<(usize, usize)> for Matrix {
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut f64 {
+        &mut self.data[i * self.cols + j]
+    }
+}
+
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows: Vec<String> = self
+            .iter_rows()
+            .map(|row| {
+                row.iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .collect();
+        write!(f, "[{}]", rows.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix() {
+        let a = Matrix::new(2, 2, vec![1.0, 2.0, 3.0, 4.0]);
+        let b = Matrix::new(2, 1, vec![5.0, 6.0]);
+        assert_eq!(a.matmul(&b), Ok(Matrix::new(2, 1, vec![17.0, 39.0])));
+        assert_eq!(
+            b.matmul(&a),
+            Err(InterpreterErrorKind::ShapeMismatch {
+                left: (2, 1),
+                right: (2, 2)
+            })
+        );
+        assert_eq!(a.transpose(), Matrix::new(2, 2, vec![1.0, 3.0, 2.0, 4.0]));
+        assert_eq!(a.det(), Ok(-2.0));
+        // 逆行列は丸め誤差が出るので近似で比べる
+        let approx_eq =
+            |m: &Matrix, data: &[f64]| m.data.iter().zip(data).all(|(x, y)| (x - y).abs() < 1e-9);
+        let inv = a.inverse().unwrap();
+        assert!(approx_eq(&inv, &[-2.0, 1.0, 1.5, -0.5]));
+        assert!(approx_eq(&a.matmul(&inv).unwrap(), &[1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(
+            Matrix::new(2, 2, vec![1.0, 2.0, 2.0, 4.0]).inverse(),
+            Err(InterpreterErrorKind::SingularMatrix)
+        );
+        assert_eq!(b.det(), Err(InterpreterErrorKind::NotSquare((2, 1))));
+        assert_eq!(a.to_string(), "[1, 2; 3, 4]");
+    }
+}
@@ -0,0 +1,786 @@
+use std::fmt;
+use std::iter::Peekable;
+
+use crate::lexer::{Token, TokenKind};
+use crate::units;
+use crate::{Annot, Loc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AstKind {
+    Num(u64),
+    Float(f64),
+    Imaginary(f64),                                //虚数リテラル
+    Matrix(Vec<Vec<Ast>>),                         //行列リテラル（行ごとの要素）
+    Str(String),                                   //文字列リテラル
+    Var(String),                                   //変数参照
+    WithUnit { e: Box<Ast>, unit: Box<Ast> },      //単位つきの数値リテラル（3 m, 20 km/h）
+    Convert { e: Box<Ast>, unit: Box<Ast> },       //単位変換（e in unit）
+    Call { name: String, args: Vec<Ast> },         //関数呼び出し
+    UniOp { op: UniOp, e: Box<Ast> },              //単項演算
+    BinOp { op: BinOp, l: Box<Ast>, r: Box<Ast> }, //二項演算
+} // 木構造を表す
+
+// ex:
+// 1 + 2 * 3
+
+pub type Ast = Annot<AstKind>;
+impl Ast {
+    pub fn num(n: u64, loc: Loc) -> Self {
+        Self::new(AstKind::Num(n), loc)
+    }
+    pub fn float(x: f64, loc: Loc) -> Self {
+        Self::new(AstKind::Float(x), loc)
+    }
+    pub fn imaginary(x: f64, loc: Loc) -> Self {
+        Self::new(AstKind::Imaginary(x), loc)
+    }
+    pub fn matrix(rows: Vec<Vec<Ast>>, loc: Loc) -> Self {
+        Self::new(AstKind::Matrix(rows), loc)
+    }
+    pub fn with_unit(e: Ast, unit: Ast, loc: Loc) -> Self {
+        Self::new(
+            AstKind::WithUnit {
+                e: Box::new(e),
+                unit: Box::new(unit),
+            },
+            loc,
+        )
+    }
+    pub fn convert(e: Ast, unit: Ast, loc: Loc) -> Self {
+        Self::new(
+            AstKind::Convert {
+                e: Box::new(e),
+                unit: Box::new(unit),
+            },
+            loc,
+        )
+    }
+    pub fn string(s: impl Into<String>, loc: Loc) -> Self {
+        Self::new(AstKind::Str(s.into()), loc)
+    }
+    pub fn var(name: impl Into<String>, loc: Loc) -> Self {
+        Self::new(AstKind::Var(name.into()), loc)
+    }
+    pub fn call(name: impl Into<String>, args: Vec<Ast>, loc: Loc) -> Self {
+        Self::new(
+            AstKind::Call {
+                name: name.into(),
+                args,
+            },
+            loc,
+        )
+    }
+    pub fn uniop(op: UniOp, e: Ast, loc: Loc) -> Self {
+        Self::new(AstKind::UniOp { op, e: Box::new(e) }, loc)
+    }
+    pub fn binop(op: BinOp, l: Ast, r: Ast, loc: Loc) -> Self {
+        Self::new(
+            AstKind::BinOp {
+                op,
+                l: Box::new(l),
+                r: Box::new(r),
+            },
+            loc,
+        )
+    }
+
+    // 子ノードを左から順に返す
+    pub fn children(&self) -> Vec<&Ast> {
+        use self::AstKind::*;
+        match &self.value {
+            Num(_) | Float(_) | Imaginary(_) | Str(_) | Var(_) => vec![],
+            Matrix(rows) => rows.iter().flatten().collect(),
+            WithUnit { e, unit } | Convert { e, unit } => vec![e, unit],
+            Call { args, .. } => args.iter().collect(),
+            UniOp { e, .. } => vec![e],
+            BinOp { l, r, .. } => vec![l, r],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UniOpKind {
+    Plus,
+    Minus,
+    Not, // ビット反転 ~
+}
+pub type UniOp = Annot<UniOpKind>;
+impl UniOp {
+    pub fn plus(loc: Loc) -> Self {
+        Self::new(UniOpKind::Plus, loc)
+    }
+    pub fn minus(loc: Loc) -> Self {
+        Self::new(UniOpKind::Minus, loc)
+    }
+    pub fn not(loc: Loc) -> Self {
+        Self::new(UniOpKind::Not, loc)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BinOpKind {
+    Add,
+    Sub,
+    Mult,
+    Div,
+    BitAnd, // &
+    BitOr,  // |
+    BitXor, // ^
+    Shl,    // <<
+    Shr,    // >>
+}
+
+impl BinOpKind {
+    // 整数にしか使えないビット演算か
+    pub fn is_bitwise(&self) -> bool {
+        use self::BinOpKind::*;
+        matches!(self, BitAnd | BitOr | BitXor | Shl | Shr)
+    }
+}
+
+pub type BinOp = Annot<BinOpKind>;
+impl BinOp {
+    pub fn add(loc: Loc) -> Self {
+        Self::new(BinOpKind::Add, loc)
+    }
+    pub fn sub(loc: Loc) -> Self {
+        Self::new(BinOpKind::Sub, loc)
+    }
+    pub fn mult(loc: Loc) -> Self {
+        Self::new(BinOpKind::Mult, loc)
+    }
+    pub fn div(loc: Loc) -> Self {
+        Self::new(BinOpKind::Div, loc)
+    }
+    pub fn bit_and(loc: Loc) -> Self {
+        Self::new(BinOpKind::BitAnd, loc)
+    }
+    pub fn bit_or(loc: Loc) -> Self {
+        Self::new(BinOpKind::BitOr, loc)
+    }
+    pub fn bit_xor(loc: Loc) -> Self {
+        Self::new(BinOpKind::BitXor, loc)
+    }
+    pub fn shl(loc: Loc) -> Self {
+        Self::new(BinOpKind::Shl, loc)
+    }
+    pub fn shr(loc: Loc) -> Self {
+        Self::new(BinOpKind::Shr, loc)
+    }
+}
+
+// 構文解析時のエラー
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnExpectedToken(Token),     //予期しないトークンがきた
+    NotExpression(Token),       //式を期待してたけど式以外がきた
+    NotOperator(Token),         //演算子を期待してたけど演算子以外がきた
+    UnclosedOpenParen(Token),   //括弧が閉じられていない
+    RedundantExpression(Token), //式の解析が終わったけどトークンが余ってる
+    Eof,
+}
+
+impl ParseError {
+    // エラーの原因になったトークンの位置。Eof は位置を持たない
+    pub fn loc(&self) -> Option<&Loc> {
+        use self::ParseError::*;
+        match self {
+            UnExpectedToken(tok)
+            | NotExpression(tok)
+            | NotOperator(tok)
+            | UnclosedOpenParen(tok)
+            | RedundantExpression(tok) => Some(&tok.loc),
+            Eof => None,
+        }
+    }
+}
+
+impl ParseError {
+    // 位置を含まないエラーの説明
+    pub fn message(&self) -> String {
+        use self::ParseError::*;
+        match self {
+            UnExpectedToken(tok) => format!("{} is not expected", tok.value),
+            NotExpression(tok) => format!("'{}' is not a start of expression", tok.value),
+            NotOperator(tok) => format!("'{}' is not an operator", tok.value),
+            UnclosedOpenParen(tok) => format!("'{}' is not closed", tok.value),
+            RedundantExpression(tok) => format!("expression after '{}' is redundant", tok.value),
+            Eof => "End of file".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.loc() {
+            Some(loc) => write!(f, "{}: {}", loc, self.message()),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// EXPR ;
+pub fn parse(tokens: Vec<Token>) -> Result<Ast, ParseError> {
+    let mut tokens = tokens.into_iter().peekable();
+    //iter.peek()可能な iteratorを作る（iter.next() と違いイテレータを消費しないので先読みが可能）
+
+    let ret = parse_expr(&mut tokens)?;
+    match tokens.next() {
+        Some(tok) => Err(ParseError::RedundantExpression(tok)),
+        None => Ok(ret),
+    }
+}
+
+// EXPR = BITOR, ["in", EXPR3] ;
+fn parse_expr<Tokens>(tokens: &mut Peekable<Tokens>) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    let e = parse_bit_or(tokens)?;
+    match tokens.peek().map(|tok| &tok.value) {
+        Some(TokenKind::In) => {
+            tokens.next();
+            let unit = parse_expr3(tokens)?;
+            let loc = e.loc.merge(&unit.loc);
+            Ok(Ast::convert(e, unit, loc))
+        }
+        _ => Ok(e),
+    }
+}
+
+// ビット演算の優先順位は Rust と同じで、低い方から | ^ & << >> の順
+// BITOR = BITXOR, ("|", BITXOR)* ;
+fn parse_bit_or<Tokens>(tokens: &mut Peekable<Tokens>) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    parse_left_binop(tokens, parse_bit_xor, |tokens| {
+        let op = tokens
+            .peek()
+            .ok_or(ParseError::Eof)
+            .and_then(|tok| match tok.value {
+                TokenKind::Pipe => Ok(BinOp::bit_or(tok.loc.clone())),
+                _ => Err(ParseError::NotOperator(tok.clone())),
+            })?;
+        tokens.next();
+        Ok(op)
+    })
+}
+
+// BITXOR = BITAND, ("^", BITAND)* ;
+fn parse_bit_xor<Tokens>(tokens: &mut Peekable<Tokens>) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    parse_left_binop(tokens, parse_bit_and, |tokens| {
+        let op = tokens
+            .peek()
+            .ok_or(ParseError::Eof)
+            .and_then(|tok| match tok.value {
+                TokenKind::Caret => Ok(BinOp::bit_xor(tok.loc.clone())),
+                _ => Err(ParseError::NotOperator(tok.clone())),
+            })?;
+        tokens.next();
+        Ok(op)
+    })
+}
+
+// BITAND = SHIFT, ("&", SHIFT)* ;
+fn parse_bit_and<Tokens>(tokens: &mut Peekable<Tokens>) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    parse_left_binop(tokens, parse_shift, |tokens| {
+        let op = tokens
+            .peek()
+            .ok_or(ParseError::Eof)
+            .and_then(|tok| match tok.value {
+                TokenKind::Ampersand => Ok(BinOp::bit_and(tok.loc.clone())),
+                _ => Err(ParseError::NotOperator(tok.clone())),
+            })?;
+        tokens.next();
+        Ok(op)
+    })
+}
+
+// SHIFT = EXPR3, (("<<" | ">>"), EXPR3)* ;
+fn parse_shift<Tokens>(tokens: &mut Peekable<Tokens>) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    parse_left_binop(tokens, parse_expr3, |tokens| {
+        let op = tokens
+            .peek()
+            .ok_or(ParseError::Eof)
+            .and_then(|tok| match tok.value {
+                TokenKind::Shl => Ok(BinOp::shl(tok.loc.clone())),
+                TokenKind::Shr => Ok(BinOp::shr(tok.loc.clone())),
+                _ => Err(ParseError::NotOperator(tok.clone())),
+            })?;
+        tokens.next();
+        Ok(op)
+    })
+}
+
+// EXPR3 = EXPR2 EXPR3_Loop
+// EXPR3_Loop = ("+"|"-") EXPR2 EXPR3_Loop | ε
+fn parse_expr3<Tokens>(tokens: &mut Peekable<Tokens>) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    parse_left_binop(tokens, parse_expr2, |tokens| {
+        let op = tokens.peek().map_or_else(
+            || Err(ParseError::Eof),
+            |tok| match tok {
+                Token {
+                    value: TokenKind::Plus,
+                    loc,
+                } => Ok(BinOp::add(loc.clone())),
+                Token {
+                    value: TokenKind::Minus,
+                    loc,
+                } => Ok(BinOp::sub(loc.clone())),
+                _ => Err(ParseError::NotOperator(tok.clone())),
+            },
+        )?; // Err の場合は return して関数を抜ける
+        tokens.next();
+        Ok(op)
+    })
+
+    // let mut e = parse_expr2(tokens)?;
+
+    // loop {
+    //     match tokens.peek().map(|tok| tok.value) {
+    //         Some(TokenKind::Plus) | Some(TokenKind::Minus) => {
+    //             let op = match tokens.next().unwrap() {
+    //                 Token {
+    //                     value: TokenKind::Plus,
+    //                     loc,
+    //                 } => BinOp::add(loc),
+    //                 Token {
+    //                     value: TokenKind::Minus,
+    //                     loc,
+    //                 } => BinOp::sub(loc),
+    //                 _ => unreachable!(),
+    //             };
+    //             let r = parse_expr2(tokens)?;
+    //             let loc = e.loc.merge(&r.loc);
+    //             e = Ast::binop(op, e, r, loc)
+    //         }
+    //         //  ε
+    //         _ => return Ok(e),
+    //     }
+    // }
+}
+
+// EXPR2 = EXPR1 EXPR2_Loop
+// EXPR2_Loop = ("*"|"/") EXPR1 EXPR2_Loop | ε
+fn parse_expr2<Tokens>(tokens: &mut Peekable<Tokens>) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    parse_left_binop(tokens, parse_expr1, |tokens| {
+        let op = tokens
+            .peek()
+            .ok_or(ParseError::Eof)
+            .and_then(|tok| match tok.value {
+                TokenKind::Asterisk => Ok(BinOp::mult(tok.loc.clone())),
+                TokenKind::Slash => Ok(BinOp::div(tok.loc.clone())),
+                _ => Err(ParseError::NotOperator(tok.clone())),
+            })?;
+
+        tokens.next();
+        Ok(op)
+    })
+    // let mut e = parse_expr1(tokens)?;
+
+    // loop {
+    //     match tokens.peek().map(|tok| tok.value) {
+    //         Some(TokenKind::Asterisk) | Some(TokenKind::Slash) => {
+    //             let op = match tokens.next().unwrap() {
+    //                 Token {
+    //                     value: TokenKind::Asterisk,
+    //                     loc,
+    //                 } => BinOp::mult(loc),
+    //                 Token {
+    //                     value: TokenKind::Slash,
+    //                     loc,
+    //                 } => BinOp::div(loc),
+    //                 _ => unreachable!(),
+    //             };
+    //             let r = parse_expr1(tokens)?;
+    //             let loc = e.loc.merge(&r.loc);
+    //             e = Ast::binop(op, e, r, loc)
+    //         }
+    //         _ => return Ok(e),
+    //     }
+    // }
+}
+
+// EXPR1 = ("+" | "-" | "~"), ATOM | ATOM ;
+fn parse_expr1<Tokens>(tokens: &mut Peekable<Tokens>) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    match tokens.peek().map(|tok| &tok.value) {
+        Some(TokenKind::Plus) | Some(TokenKind::Minus) | Some(TokenKind::Tilde) => {
+            let op = match tokens.next() {
+                Some(Token {
+                    value: TokenKind::Plus,
+                    loc,
+                }) => UniOp::plus(loc),
+                Some(Token {
+                    value: TokenKind::Minus,
+                    loc,
+                }) => UniOp::minus(loc),
+                Some(Token {
+                    value: TokenKind::Tilde,
+                    loc,
+                }) => UniOp::not(loc),
+                _ => unreachable!(),
+            };
+            let e = parse_atom(tokens)?;
+            let loc = op.loc.merge(&e.loc);
+            Ok(Ast::uniop(op, e, loc))
+        }
+        _ => parse_atom(tokens),
+    }
+}
+
+// ATOM = NUMBER, [UNIT] | IMAGINARY | STRING | IDENT | IDENT, "(", ARGS, ")" | "(", EXPR3, ")" | MATRIX ;
+// NUMBER = UNUMBER | UFLOAT ;
+// UNIT = IDENT, (("*" | "/"), IDENT)* ;
+fn parse_atom<Tokens>(tokens: &mut Peekable<Tokens>) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    tokens
+        .next()
+        .ok_or(ParseError::Eof)
+        .and_then(|tok| match tok.value {
+            TokenKind::Number(n) => parse_unit_suffix(tokens, Ast::num(n, tok.loc)),
+            TokenKind::Float(x) => parse_unit_suffix(tokens, Ast::float(x, tok.loc)),
+            TokenKind::Imaginary(x) => Ok(Ast::imaginary(x, tok.loc)),
+            TokenKind::Str(s) => Ok(Ast::string(s, tok.loc)),
+            TokenKind::LBracket => parse_matrix(tokens, tok),
+            TokenKind::Ident(name) => match tokens.peek().map(|tok| &tok.value) {
+                Some(TokenKind::LParen) => {
+                    let lparen = tokens.next().unwrap();
+                    let (args, rparen) = parse_args(tokens, lparen)?;
+                    Ok(Ast::call(name, args, tok.loc.merge(&rparen)))
+                }
+                _ => Ok(Ast::var(name, tok.loc)),
+            },
+            TokenKind::LParen => {
+                let e = parse_expr(tokens)?;
+                match tokens.next() {
+                    Some(Token {
+                        value: TokenKind::RParen,
+                        ..
+                    }) => Ok(e),
+                    Some(t) => Err(ParseError::RedundantExpression(t)),
+                    _ => Err(ParseError::UnclosedOpenParen(tok)),
+                }
+            }
+            _ => Err(ParseError::NotExpression(tok)),
+        })
+}
+
+// 数値の直後に単位表にある識別子があれば単位とみなす（3 m, 20 km/h）
+// 単位は変数とは別に、空白を挟まない * と / でつないだ識別子をまとめて読む
+// 空白を挟んだ 1 m / t は (1 m) / t になる
+fn parse_unit_suffix<Tokens>(tokens: &mut Peekable<Tokens>, e: Ast) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    let mut unit = match tokens.next_if(|tok| matches!(tok.value, TokenKind::Ident(_))) {
+        Some(Token {
+            value: TokenKind::Ident(name),
+            loc,
+        }) if units::lookup(&name).is_some() => Ast::var(name, loc),
+        // 単位でない識別子（2 x）は演算子の書き忘れとみなす
+        Some(tok) => return Err(ParseError::NotOperator(tok)),
+        None => return Ok(e),
+    };
+    loop {
+        let end = unit.loc.1;
+        let op = match tokens.next_if(|tok| {
+            tok.loc.0 == end && matches!(tok.value, TokenKind::Asterisk | TokenKind::Slash)
+        }) {
+            Some(Token {
+                value: TokenKind::Asterisk,
+                loc,
+            }) => BinOp::mult(loc),
+            Some(Token { loc, .. }) => BinOp::div(loc),
+            None => break,
+        };
+        match tokens.next() {
+            Some(Token {
+                value: TokenKind::Ident(name),
+                loc,
+            }) => {
+                let loc_all = unit.loc.merge(&loc);
+                unit = Ast::binop(op, unit, Ast::var(name, loc), loc_all);
+            }
+            Some(tok) => return Err(ParseError::NotExpression(tok)),
+            None => return Err(ParseError::Eof),
+        }
+    }
+    let loc = e.loc.merge(&unit.loc);
+    Ok(Ast::with_unit(e, unit, loc))
+}
+
+// MATRIX = "[", ROW, (";", ROW)*, "]" ;
+// ROW = EXPR, (",", EXPR)* ;
+fn parse_matrix<Tokens>(tokens: &mut Peekable<Tokens>, lbracket: Token) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    let mut rows = vec![vec![]];
+
+    loop {
+        rows.last_mut().unwrap().push(parse_expr(tokens)?);
+        match tokens.next() {
+            Some(Token {
+                value: TokenKind::Comma,
+                ..
+            }) => continue,
+            Some(Token {
+                value: TokenKind::Semicolon,
+                ..
+            }) => rows.push(vec![]),
+            Some(Token {
+                value: TokenKind::RBracket,
+                loc,
+            }) => return Ok(Ast::matrix(rows, lbracket.loc.merge(&loc))),
+            Some(t) => return Err(ParseError::UnExpectedToken(t)),
+            None => return Err(ParseError::UnclosedOpenParen(lbracket)),
+        }
+    }
+}
+
+// ARGS = ε | EXPR3, ("," EXPR3)* ;
+// 引数の列と閉じ括弧の位置を返す
+fn parse_args<Tokens>(
+    tokens: &mut Peekable<Tokens>,
+    lparen: Token,
+) -> Result<(Vec<Ast>, Loc), ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    let mut args = vec![];
+
+    if let Some(Token {
+        value: TokenKind::RParen,
+        ..
+    }) = tokens.peek()
+    {
+        let rparen = tokens.next().unwrap();
+        return Ok((args, rparen.loc));
+    }
+
+    loop {
+        args.push(parse_expr(tokens)?);
+        match tokens.next() {
+            Some(Token {
+                value: TokenKind::Comma,
+                ..
+            }) => continue,
+            Some(Token {
+                value: TokenKind::RParen,
+                loc,
+            }) => return Ok((args, loc)),
+            Some(t) => return Err(ParseError::UnExpectedToken(t)),
+            None => return Err(ParseError::UnclosedOpenParen(lparen)),
+        }
+    }
+}
+
+fn parse_left_binop<Tokens>(
+    tokens: &mut Peekable<Tokens>,
+    subexpr_parser: fn(&mut Peekable<Tokens>) -> Result<Ast, ParseError>,
+    op_parser: fn(&mut Peekable<Tokens>) -> Result<BinOp, ParseError>,
+) -> Result<Ast, ParseError>
+where
+    Tokens: Iterator<Item = Token>,
+{
+    let mut e = subexpr_parser(tokens)?;
+
+    while tokens.peek().is_some() {
+        let op = match op_parser(tokens) {
+            Ok(op) => op,
+            Err(_) => break,
+        };
+        let r = subexpr_parser(tokens)?;
+        let loc = e.loc.merge(&r.loc);
+        e = Ast::binop(op, e, r, loc)
+    }
+    Ok(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    #[test]
+    fn test_parser() {
+        let ast = parse(lex("1 + 2 * 3").unwrap()).unwrap();
+        println!("{:?}", ast);
+        assert_eq!(
+            ast,
+            Ast::binop(
+                BinOp::add(Loc(2, 3)),
+                Ast::num(1, Loc(0, 1)),
+                Ast::binop(
+                    BinOp::mult(Loc(6, 7)),
+                    Ast::num(2, Loc(4, 5)),
+                    Ast::num(3, Loc(8, 9)),
+                    Loc(4, 9)
+                ),
+                Loc(0, 9)
+            )
+        );
+        let ast: Annot<AstKind> = parse(lex("(1 + 2) * 3 + 4").unwrap()).unwrap();
+        println!("{:?}", ast);
+        assert_eq!(
+            ast,
+            Ast::binop(
+                BinOp::add(Loc(12, 13)),
+                Ast::binop(
+                    BinOp::mult(Loc(8, 9)),
+                    Ast::binop(
+                        BinOp::add(Loc(3, 4)),
+                        Ast::num(1, Loc(1, 2)),
+                        Ast::num(2, Loc(5, 6)),
+                        Loc(1, 6)
+                    ),
+                    Ast::num(3, Loc(10, 11)),
+                    Loc(1, 11)
+                ),
+                Ast::num(4, Loc(14, 15)),
+                Loc(1, 15)
+            )
+        );
+    }
+
+    #[test]
+    fn test_parser_bitwise() {
+        // 1 | (2 ^ (3 & (~4 << 5)))
+        let ast = parse(lex("1 | 2 ^ 3 & ~4 << 5").unwrap()).unwrap();
+        assert_eq!(
+            ast,
+            Ast::binop(
+                BinOp::bit_or(Loc(2, 3)),
+                Ast::num(1, Loc(0, 1)),
+                Ast::binop(
+                    BinOp::bit_xor(Loc(6, 7)),
+                    Ast::num(2, Loc(4, 5)),
+                    Ast::binop(
+                        BinOp::bit_and(Loc(10, 11)),
+                        Ast::num(3, Loc(8, 9)),
+                        Ast::binop(
+                            BinOp::shl(Loc(15, 17)),
+                            Ast::uniop(
+                                UniOp::not(Loc(12, 13)),
+                                Ast::num(4, Loc(13, 14)),
+                                Loc(12, 14)
+                            ),
+                            Ast::num(5, Loc(18, 19)),
+                            Loc(12, 19)
+                        ),
+                        Loc(8, 19)
+                    ),
+                    Loc(4, 19)
+                ),
+                Loc(0, 19)
+            )
+        );
+        // シフトより + が先
+        let ast = parse(lex("1 << 2 + 3").unwrap()).unwrap();
+        assert!(matches!(ast.value, AstKind::BinOp { op, .. } if op.value == BinOpKind::Shl));
+    }
+
+    #[test]
+    fn test_parser_call() {
+        let ast = parse(lex("max(x, 2) - f()").unwrap()).unwrap();
+        assert_eq!(
+            ast,
+            Ast::binop(
+                BinOp::sub(Loc(10, 11)),
+                Ast::call(
+                    "max",
+                    vec![Ast::var("x", Loc(4, 5)), Ast::num(2, Loc(7, 8))],
+                    Loc(0, 9)
+                ),
+                Ast::call("f", vec![], Loc(12, 15)),
+                Loc(0, 15)
+            )
+        );
+        assert_eq!(
+            parse(lex("f(1 2)").unwrap()),
+            Err(ParseError::UnExpectedToken(Token::number(2, Loc(4, 5))))
+        );
+        assert_eq!(parse(lex("f(1,").unwrap()), Err(ParseError::Eof));
+    }
+
+    #[test]
+    fn test_parser_matrix() {
+        let ast = parse(lex("[1, 2i; -3, 4]").unwrap()).unwrap();
+        assert_eq!(
+            ast,
+            Ast::matrix(
+                vec![
+                    vec![Ast::num(1, Loc(1, 2)), Ast::imaginary(2.0, Loc(4, 6))],
+                    vec![
+                        Ast::uniop(UniOp::minus(Loc(8, 9)), Ast::num(3, Loc(9, 10)), Loc(8, 10)),
+                        Ast::num(4, Loc(12, 13))
+                    ],
+                ],
+                Loc(0, 14)
+            )
+        );
+        assert_eq!(
+            parse(lex("[1, 2").unwrap()),
+            Err(ParseError::UnclosedOpenParen(Token::lbracket(Loc(0, 1))))
+        );
+    }
+
+    #[test]
+    fn test_parser_unit() {
+        let ast = parse(lex("1.5 km/h in m/s").unwrap()).unwrap();
+        assert_eq!(
+            ast,
+            Ast::convert(
+                Ast::with_unit(
+                    Ast::float(1.5, Loc(0, 3)),
+                    Ast::binop(
+                        BinOp::div(Loc(6, 7)),
+                        Ast::var("km", Loc(4, 6)),
+                        Ast::var("h", Loc(7, 8)),
+                        Loc(4, 8)
+                    ),
+                    Loc(0, 8)
+                ),
+                Ast::binop(
+                    BinOp::div(Loc(13, 14)),
+                    Ast::var("m", Loc(12, 13)),
+                    Ast::var("s", Loc(14, 15)),
+                    Loc(12, 15)
+                ),
+                Loc(0, 15)
+            )
+        );
+        // 数値の後の識別子は、単位表になければ演算子の書き忘れとして扱う
+        assert_eq!(
+            parse(lex("2 x").unwrap()),
+            Err(ParseError::NotOperator(Token::ident("x", Loc(2, 3))))
+        );
+        assert_eq!(
+            parse(lex("3 parsec").unwrap()),
+            Err(ParseError::NotOperator(Token::ident("parsec", Loc(2, 8))))
+        );
+    }
+}
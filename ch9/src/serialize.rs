@@ -0,0 +1,693 @@
+use std::fmt;
+
+use crate::asm::{CodegenError, CodegenErrorKind};
+use crate::interpreter::InterpreterErrorKind;
+use crate::json::Json;
+use crate::lexer::{LexErrorKind, Token, TokenKind};
+use crate::parser::{Ast, AstKind, BinOpKind, ParseError, UniOpKind};
+use crate::sexpr::Sexpr;
+use crate::units::Dim;
+use crate::{Annot, Error, Loc};
+
+// 構文解析の結果などを他のツールに渡すための変換
+// JSON で読み書きする方法を実装すれば、S 式は JSON を経由して読み書きできる
+pub trait Serialize: Sized {
+    fn to_json(&self) -> Json;
+    fn from_json(json: &Json) -> Result<Self, DecodeError>;
+
+    fn to_sexpr(&self) -> Sexpr {
+        Sexpr::from_json(&self.to_json())
+    }
+
+    fn from_sexpr(s: &Sexpr) -> Result<Self, DecodeError> {
+        let json = s
+            .to_json()
+            .map_err(|e| DecodeError(format!("invalid s-expression: {}", e.message)))?;
+        Self::from_json(&json)
+    }
+}
+
+// 読み込んだ値の形が期待と違った
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError(pub String);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json, DecodeError> {
+    json.get(key)
+        .ok_or_else(|| DecodeError(format!("missing field '{}'", key)))
+}
+
+fn str_field<'a>(json: &'a Json, key: &str) -> Result<&'a str, DecodeError> {
+    field(json, key)?
+        .as_str()
+        .ok_or_else(|| DecodeError(format!("field '{}' must be a string", key)))
+}
+
+fn f64_field(json: &Json, key: &str) -> Result<f64, DecodeError> {
+    field(json, key)?
+        .as_f64()
+        .ok_or_else(|| DecodeError(format!("field '{}' must be a number", key)))
+}
+
+// f64 で正確に表せない大きな整数は文字列にする
+fn u64_to_json(n: u64) -> Json {
+    if n <= 1 << 53 {
+        Json::from(n as f64)
+    } else {
+        Json::from(n.to_string())
+    }
+}
+
+fn u64_field(json: &Json, key: &str) -> Result<u64, DecodeError> {
+    let v = field(json, key)?;
+    v.as_u64()
+        .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+        .ok_or_else(|| DecodeError(format!("field '{}' must be an integer", key)))
+}
+
+fn usize_field(json: &Json, key: &str) -> Result<usize, DecodeError> {
+    u64_field(json, key).map(|n| n as usize)
+}
+
+fn array_field<'a>(json: &'a Json, key: &str) -> Result<&'a [Json], DecodeError> {
+    field(json, key)?
+        .as_array()
+        .ok_or_else(|| DecodeError(format!("field '{}' must be an array", key)))
+}
+
+fn unknown_kind(kind: &str) -> DecodeError {
+    DecodeError(format!("unknown kind '{}'", kind))
+}
+
+// エラーの中で &'static str として持っている型名など。読み込むときはこの中から探す
+const STATIC_NAMES: &[&str] = &[
+    "int",
+    "float",
+    "quantity",
+    "complex",
+    "matrix",
+    "real",
+    "string",
+    "number",
+    "complex number",
+    "variable",
+    "unit",
+    "function call",
+    "bitwise operator",
+    "i8",
+    "i16",
+    "i32",
+    "i64",
+    "i128",
+    "u8",
+    "u16",
+    "u32",
+    "u64",
+    "u128",
+];
+
+fn static_field(json: &Json, key: &str) -> Result<&'static str, DecodeError> {
+    let s = str_field(json, key)?;
+    STATIC_NAMES
+        .iter()
+        .find(|&&name| name == s)
+        .copied()
+        .ok_or_else(|| DecodeError(format!("unknown name '{}'", s)))
+}
+
+// Loc は [start, end]
+impl Serialize for Loc {
+    fn to_json(&self) -> Json {
+        Json::from(vec![Json::from(self.0), Json::from(self.1)])
+    }
+
+    fn from_json(json: &Json) -> Result<Self, DecodeError> {
+        match json.as_array() {
+            Some([s, e]) => match (s.as_u64(), e.as_u64()) {
+                (Some(s), Some(e)) => Ok(Loc(s as usize, e as usize)),
+                _ => Err(DecodeError("loc must be a pair of integers".to_string())),
+            },
+            _ => Err(DecodeError("loc must be a pair of integers".to_string())),
+        }
+    }
+}
+
+// Annot<T> の T 側。種類の名前とフィールドに分けて読み書きする
+// Annot<T> は {"kind": 種類, "loc": 位置, フィールド...} になる
+pub trait Tagged: Sized {
+    fn encode(&self) -> (&'static str, Vec<(&'static str, Json)>);
+    fn decode(kind: &str, json: &Json) -> Result<Self, DecodeError>;
+}
+
+impl<T: Tagged> Serialize for Annot<T> {
+    fn to_json(&self) -> Json {
+        let (kind, fields) = self.value.encode();
+        let mut all = vec![("kind", Json::from(kind)), ("loc", self.loc.to_json())];
+        all.extend(fields);
+        Json::object(all)
+    }
+
+    fn from_json(json: &Json) -> Result<Self, DecodeError> {
+        let kind = str_field(json, "kind")?;
+        let loc = Loc::from_json(field(json, "loc")?)?;
+        Ok(Annot::new(T::decode(kind, json)?, loc))
+    }
+}
+
+impl Tagged for TokenKind {
+    fn encode(&self) -> (&'static str, Vec<(&'static str, Json)>) {
+        use self::TokenKind::*;
+        match self {
+            Number(n) => ("number", vec![("value", u64_to_json(*n))]),
+            Float(x) => ("float", vec![("value", Json::from(*x))]),
+            Imaginary(x) => ("imaginary", vec![("value", Json::from(*x))]),
+            Ident(name) => ("ident", vec![("name", Json::from(name.as_str()))]),
+            Str(s) => ("string", vec![("value", Json::from(s.as_str()))]),
+            In => ("in", vec![]),
+            Plus => ("plus", vec![]),
+            Minus => ("minus", vec![]),
+            Asterisk => ("asterisk", vec![]),
+            Slash => ("slash", vec![]),
+            Ampersand => ("ampersand", vec![]),
+            Pipe => ("pipe", vec![]),
+            Caret => ("caret", vec![]),
+            Shl => ("shl", vec![]),
+            Shr => ("shr", vec![]),
+            Tilde => ("tilde", vec![]),
+            LParen => ("lparen", vec![]),
+            RParen => ("rparen", vec![]),
+            LBracket => ("lbracket", vec![]),
+            RBracket => ("rbracket", vec![]),
+            Comma => ("comma", vec![]),
+            Semicolon => ("semicolon", vec![]),
+        }
+    }
+
+    fn decode(kind: &str, json: &Json) -> Result<Self, DecodeError> {
+        use self::TokenKind::*;
+        Ok(match kind {
+            "number" => Number(u64_field(json, "value")?),
+            "float" => Float(f64_field(json, "value")?),
+            "imaginary" => Imaginary(f64_field(json, "value")?),
+            "ident" => Ident(str_field(json, "name")?.to_string()),
+            "string" => Str(str_field(json, "value")?.to_string()),
+            "in" => In,
+            "plus" => Plus,
+            "minus" => Minus,
+            "asterisk" => Asterisk,
+            "slash" => Slash,
+            "ampersand" => Ampersand,
+            "pipe" => Pipe,
+            "caret" => Caret,
+            "shl" => Shl,
+            "shr" => Shr,
+            "tilde" => Tilde,
+            "lparen" => LParen,
+            "rparen" => RParen,
+            "lbracket" => LBracket,
+            "rbracket" => RBracket,
+            "comma" => Comma,
+            "semicolon" => Semicolon,
+            _ => return Err(unknown_kind(kind)),
+        })
+    }
+}
+
+impl Tagged for UniOpKind {
+    fn encode(&self) -> (&'static str, Vec<(&'static str, Json)>) {
+        match self {
+            UniOpKind::Plus => ("plus", vec![]),
+            UniOpKind::Minus => ("minus", vec![]),
+            UniOpKind::Not => ("not", vec![]),
+        }
+    }
+
+    fn decode(kind: &str, _: &Json) -> Result<Self, DecodeError> {
+        match kind {
+            "plus" => Ok(UniOpKind::Plus),
+            "minus" => Ok(UniOpKind::Minus),
+            "not" => Ok(UniOpKind::Not),
+            _ => Err(unknown_kind(kind)),
+        }
+    }
+}
+
+impl Tagged for BinOpKind {
+    fn encode(&self) -> (&'static str, Vec<(&'static str, Json)>) {
+        match self {
+            BinOpKind::Add => ("add", vec![]),
+            BinOpKind::Sub => ("sub", vec![]),
+            BinOpKind::Mult => ("mult", vec![]),
+            BinOpKind::Div => ("div", vec![]),
+            BinOpKind::BitAnd => ("bit_and", vec![]),
+            BinOpKind::BitOr => ("bit_or", vec![]),
+            BinOpKind::BitXor => ("bit_xor", vec![]),
+            BinOpKind::Shl => ("shl", vec![]),
+            BinOpKind::Shr => ("shr", vec![]),
+        }
+    }
+
+    fn decode(kind: &str, _: &Json) -> Result<Self, DecodeError> {
+        match kind {
+            "add" => Ok(BinOpKind::Add),
+            "sub" => Ok(BinOpKind::Sub),
+            "mult" => Ok(BinOpKind::Mult),
+            "div" => Ok(BinOpKind::Div),
+            "bit_and" => Ok(BinOpKind::BitAnd),
+            "bit_or" => Ok(BinOpKind::BitOr),
+            "bit_xor" => Ok(BinOpKind::BitXor),
+            "shl" => Ok(BinOpKind::Shl),
+            "shr" => Ok(BinOpKind::Shr),
+            _ => Err(unknown_kind(kind)),
+        }
+    }
+}
+
+impl Tagged for AstKind {
+    fn encode(&self) -> (&'static str, Vec<(&'static str, Json)>) {
+        use self::AstKind::*;
+        let list = |asts: &[Ast]| Json::from(asts.iter().map(Ast::to_json).collect::<Vec<_>>());
+        match self {
+            Num(n) => ("num", vec![("value", u64_to_json(*n))]),
+            Float(x) => ("float", vec![("value", Json::from(*x))]),
+            Imaginary(x) => ("imaginary", vec![("value", Json::from(*x))]),
+            Matrix(rows) => (
+                "matrix",
+                vec![(
+                    "rows",
+                    Json::from(rows.iter().map(|row| list(row)).collect::<Vec<_>>()),
+                )],
+            ),
+            Str(s) => ("string", vec![("value", Json::from(s.as_str()))]),
+            Var(name) => ("var", vec![("name", Json::from(name.as_str()))]),
+            WithUnit { e, unit } => (
+                "with_unit",
+                vec![("e", e.to_json()), ("unit", unit.to_json())],
+            ),
+            Convert { e, unit } => (
+                "convert",
+                vec![("e", e.to_json()), ("unit", unit.to_json())],
+            ),
+            Call { name, args } => (
+                "call",
+                vec![("name", Json::from(name.as_str())), ("args", list(args))],
+            ),
+            UniOp { op, e } => ("uniop", vec![("op", op.to_json()), ("e", e.to_json())]),
+            BinOp { op, l, r } => (
+                "binop",
+                vec![("op", op.to_json()), ("l", l.to_json()), ("r", r.to_json())],
+            ),
+        }
+    }
+
+    fn decode(kind: &str, json: &Json) -> Result<Self, DecodeError> {
+        use self::AstKind::*;
+        let ast = |key| Ast::from_json(field(json, key)?).map(Box::new);
+        let list = |items: &[Json]| items.iter().map(Ast::from_json).collect::<Result<_, _>>();
+        Ok(match kind {
+            "num" => Num(u64_field(json, "value")?),
+            "float" => Float(f64_field(json, "value")?),
+            "imaginary" => Imaginary(f64_field(json, "value")?),
+            "matrix" => Matrix(
+                array_field(json, "rows")?
+                    .iter()
+                    .map(|row| {
+                        row.as_array()
+                            .ok_or_else(|| DecodeError("matrix row must be an array".to_string()))
+                            .and_then(list)
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            "string" => Str(str_field(json, "value")?.to_string()),
+            "var" => Var(str_field(json, "name")?.to_string()),
+            "with_unit" => WithUnit {
+                e: ast("e")?,
+                unit: ast("unit")?,
+            },
+            "convert" => Convert {
+                e: ast("e")?,
+                unit: ast("unit")?,
+            },
+            "call" => Call {
+                name: str_field(json, "name")?.to_string(),
+                args: list(array_field(json, "args")?)?,
+            },
+            "uniop" => UniOp {
+                op: Serialize::from_json(field(json, "op")?)?,
+                e: ast("e")?,
+            },
+            "binop" => BinOp {
+                op: Serialize::from_json(field(json, "op")?)?,
+                l: ast("l")?,
+                r: ast("r")?,
+            },
+            _ => return Err(unknown_kind(kind)),
+        })
+    }
+}
+
+impl Tagged for LexErrorKind {
+    fn encode(&self) -> (&'static str, Vec<(&'static str, Json)>) {
+        match self {
+            LexErrorKind::InvalidChar(c) => {
+                ("invalid_char", vec![("char", Json::from(c.to_string()))])
+            }
+            LexErrorKind::NumberOverflow => ("number_overflow", vec![]),
+            LexErrorKind::UnterminatedString => ("unterminated_string", vec![]),
+            LexErrorKind::InvalidEscape(c) => {
+                ("invalid_escape", vec![("char", Json::from(c.to_string()))])
+            }
+            LexErrorKind::MissingDigits => ("missing_digits", vec![]),
+            LexErrorKind::Eof => ("eof", vec![]),
+        }
+    }
+
+    fn decode(kind: &str, json: &Json) -> Result<Self, DecodeError> {
+        let char_field = || {
+            let mut chars = str_field(json, "char")?.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(DecodeError(
+                    "field 'char' must be one character".to_string(),
+                )),
+            }
+        };
+        match kind {
+            "invalid_char" => Ok(LexErrorKind::InvalidChar(char_field()?)),
+            "number_overflow" => Ok(LexErrorKind::NumberOverflow),
+            "unterminated_string" => Ok(LexErrorKind::UnterminatedString),
+            "invalid_escape" => Ok(LexErrorKind::InvalidEscape(char_field()?)),
+            "missing_digits" => Ok(LexErrorKind::MissingDigits),
+            "eof" => Ok(LexErrorKind::Eof),
+            _ => Err(unknown_kind(kind)),
+        }
+    }
+}
+
+fn dim_to_json(dim: &Dim) -> Json {
+    Json::from(
+        dim.0
+            .iter()
+            .map(|&e| Json::from(e as f64))
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn dim_field(json: &Json, key: &str) -> Result<Dim, DecodeError> {
+    let error = || DecodeError(format!("field '{}' must be 7 exponents", key));
+    let items = array_field(json, key)?;
+    let mut dim = Dim::NONE;
+    if items.len() != dim.0.len() {
+        return Err(error());
+    }
+    for (e, item) in dim.0.iter_mut().zip(items) {
+        *e = item
+            .as_f64()
+            .filter(|x| x.fract() == 0.0)
+            .ok_or_else(error)? as i8;
+    }
+    Ok(dim)
+}
+
+fn shape_to_json((rows, cols): (usize, usize)) -> Json {
+    Json::from(vec![Json::from(rows), Json::from(cols)])
+}
+
+fn shape_field(json: &Json, key: &str) -> Result<(usize, usize), DecodeError> {
+    let loc = Loc::from_json(field(json, key)?)
+        .map_err(|_| DecodeError(format!("field '{}' must be [rows, cols]", key)))?;
+    Ok((loc.0, loc.1))
+}
+
+impl Tagged for InterpreterErrorKind {
+    fn encode(&self) -> (&'static str, Vec<(&'static str, Json)>) {
+        use self::InterpreterErrorKind::*;
+        match self {
+            DivisionByZero => ("division_by_zero", vec![]),
+            Overflow => ("overflow", vec![]),
+            UnboundVariable(name) => (
+                "unbound_variable",
+                vec![("name", Json::from(name.as_str()))],
+            ),
+            UnknownUnit(name) => ("unknown_unit", vec![("name", Json::from(name.as_str()))]),
+            NotAUnit => ("not_a_unit", vec![]),
+            DimensionMismatch { left, right } => (
+                "dimension_mismatch",
+                vec![("left", dim_to_json(left)), ("right", dim_to_json(right))],
+            ),
+            TypeMismatch { expected, found } => (
+                "type_mismatch",
+                vec![
+                    ("expected", Json::from(*expected)),
+                    ("found", Json::from(*found)),
+                ],
+            ),
+            InvalidOperands { left, right } => (
+                "invalid_operands",
+                vec![("left", Json::from(*left)), ("right", Json::from(*right))],
+            ),
+            ShapeMismatch { left, right } => (
+                "shape_mismatch",
+                vec![
+                    ("left", shape_to_json(*left)),
+                    ("right", shape_to_json(*right)),
+                ],
+            ),
+            NotSquare(shape) => ("not_square", vec![("shape", shape_to_json(*shape))]),
+            SingularMatrix => ("singular_matrix", vec![]),
+            UndefinedFunction(name) => (
+                "undefined_function",
+                vec![("name", Json::from(name.as_str()))],
+            ),
+            ArityMismatch { expected, found } => (
+                "arity_mismatch",
+                vec![
+                    ("expected", Json::from(*expected)),
+                    ("found", Json::from(*found)),
+                ],
+            ),
+            HostError(message) => (
+                "host_error",
+                vec![("message", Json::from(message.as_str()))],
+            ),
+            ReservedName(name) => ("reserved_name", vec![("name", Json::from(name.as_str()))]),
+        }
+    }
+
+    fn decode(kind: &str, json: &Json) -> Result<Self, DecodeError> {
+        use self::InterpreterErrorKind::*;
+        let name = || str_field(json, "name").map(str::to_string);
+        Ok(match kind {
+            "division_by_zero" => DivisionByZero,
+            "overflow" => Overflow,
+            "unbound_variable" => UnboundVariable(name()?),
+            "unknown_unit" => UnknownUnit(name()?),
+            "not_a_unit" => NotAUnit,
+            "dimension_mismatch" => DimensionMismatch {
+                left: dim_field(json, "left")?,
+                right: dim_field(json, "right")?,
+            },
+            "type_mismatch" => TypeMismatch {
+                expected: static_field(json, "expected")?,
+                found: static_field(json, "found")?,
+            },
+            "invalid_operands" => InvalidOperands {
+                left: static_field(json, "left")?,
+                right: static_field(json, "right")?,
+            },
+            "shape_mismatch" => ShapeMismatch {
+                left: shape_field(json, "left")?,
+                right: shape_field(json, "right")?,
+            },
+            "not_square" => NotSquare(shape_field(json, "shape")?),
+            "singular_matrix" => SingularMatrix,
+            "undefined_function" => UndefinedFunction(name()?),
+            "arity_mismatch" => ArityMismatch {
+                expected: usize_field(json, "expected")?,
+                found: usize_field(json, "found")?,
+            },
+            "host_error" => HostError(str_field(json, "message")?.to_string()),
+            "reserved_name" => ReservedName(name()?),
+            _ => return Err(unknown_kind(kind)),
+        })
+    }
+}
+
+impl Tagged for CodegenErrorKind {
+    fn encode(&self) -> (&'static str, Vec<(&'static str, Json)>) {
+        match self {
+            CodegenErrorKind::Unsupported(what) => {
+                ("unsupported", vec![("what", Json::from(*what))])
+            }
+            CodegenErrorKind::Overflow => ("overflow", vec![]),
+        }
+    }
+
+    fn decode(kind: &str, json: &Json) -> Result<Self, DecodeError> {
+        match kind {
+            "unsupported" => Ok(CodegenErrorKind::Unsupported(static_field(json, "what")?)),
+            "overflow" => Ok(CodegenErrorKind::Overflow),
+            _ => Err(unknown_kind(kind)),
+        }
+    }
+}
+
+// ParseError は原因になったトークンを "token" に持つ
+impl Serialize for ParseError {
+    fn to_json(&self) -> Json {
+        use self::ParseError::*;
+        let (kind, tok) = match self {
+            UnExpectedToken(tok) => ("unexpected_token", tok),
+            NotExpression(tok) => ("not_expression", tok),
+            NotOperator(tok) => ("not_operator", tok),
+            UnclosedOpenParen(tok) => ("unclosed_open_paren", tok),
+            RedundantExpression(tok) => ("redundant_expression", tok),
+            Eof => return Json::object(vec![("kind", Json::from("eof"))]),
+        };
+        Json::object(vec![("kind", Json::from(kind)), ("token", tok.to_json())])
+    }
+
+    fn from_json(json: &Json) -> Result<Self, DecodeError> {
+        use self::ParseError::*;
+        let kind = str_field(json, "kind")?;
+        if kind == "eof" {
+            return Ok(Eof);
+        }
+        let tok = Token::from_json(field(json, "token")?)?;
+        Ok(match kind {
+            "unexpected_token" => UnExpectedToken(tok),
+            "not_expression" => NotExpression(tok),
+            "not_operator" => NotOperator(tok),
+            "unclosed_open_paren" => UnclosedOpenParen(tok),
+            "redundant_expression" => RedundantExpression(tok),
+            _ => return Err(unknown_kind(kind)),
+        })
+    }
+}
+
+// Error はどの段階のエラーかを "kind" に、中身を "error" に持つ
+impl Serialize for Error {
+    fn to_json(&self) -> Json {
+        let (kind, e) = match self {
+            Error::Lexer(e) => ("lexer", e.to_json()),
+            Error::Parser(e) => ("parser", e.to_json()),
+            Error::Interpreter(e) => ("interpreter", e.to_json()),
+            Error::Codegen(e) => ("codegen", e.to_json()),
+        };
+        Json::object(vec![("kind", Json::from(kind)), ("error", e)])
+    }
+
+    fn from_json(json: &Json) -> Result<Self, DecodeError> {
+        let kind = str_field(json, "kind")?;
+        let e = field(json, "error")?;
+        Ok(match kind {
+            "lexer" => Error::Lexer(Serialize::from_json(e)?),
+            "parser" => Error::Parser(Serialize::from_json(e)?),
+            "interpreter" => Error::Interpreter(Serialize::from_json(e)?),
+            "codegen" => Error::Codegen(CodegenError::from_json(e)?),
+            _ => return Err(unknown_kind(kind)),
+        })
+    }
+}
+
+// トークン列は配列として読み書きする
+impl Serialize for Vec<Token> {
+    fn to_json(&self) -> Json {
+        Json::from(self.iter().map(Token::to_json).collect::<Vec<_>>())
+    }
+
+    fn from_json(json: &Json) -> Result<Self, DecodeError> {
+        json.as_array()
+            .ok_or_else(|| DecodeError("tokens must be an array".to_string()))?
+            .iter()
+            .map(Token::from_json)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex, parse, Engine};
+
+    fn round_trip<T: Serialize + PartialEq + fmt::Debug>(v: &T) {
+        assert_eq!(
+            T::from_json(&Json::parse(&v.to_json().to_string()).unwrap()).as_ref(),
+            Ok(v)
+        );
+        assert_eq!(
+            T::from_sexpr(&Sexpr::parse(&v.to_sexpr().to_string()).unwrap()).as_ref(),
+            Ok(v)
+        );
+    }
+
+    #[test]
+    fn test_ast() {
+        let ast = parse(lex("-1 + 2").unwrap()).unwrap();
+        assert_eq!(
+            ast.to_sexpr().to_string(),
+            "(binop :loc (0 6) :op (add :loc (3 4)) \
+             :l (uniop :loc (0 2) :op (minus :loc (0 1)) :e (num :loc (1 2) :value 1)) \
+             :r (num :loc (5 6) :value 2))"
+        );
+        assert_eq!(
+            ast.to_json().to_string(),
+            r#"{"kind":"binop","loc":[0,6],"op":{"kind":"add","loc":[3,4]},"l":{"kind":"uniop","loc":[0,2],"op":{"kind":"minus","loc":[0,1]},"e":{"kind":"num","loc":[1,2],"value":1}},"r":{"kind":"num","loc":[5,6],"value":2}}"#
+        );
+
+        for src in [
+            "-1 + 2 * 3.5",
+            "~0xff & x | 1 << 2 >> 1 ^ 3",
+            "f(x, [1, 2; 3, 4]) / 2i",
+            "upper(\"a\\n\\\"b\\\"\") + \"é\"",
+            "20 km/h in m/s",
+            "18446744073709551615",
+        ] {
+            let tokens = lex(src).unwrap();
+            round_trip(&tokens);
+            round_trip(&parse(tokens).unwrap());
+        }
+        assert_eq!(
+            Ast::from_json(&Json::parse(r#"{"kind":"num","loc":[0,1]}"#).unwrap()),
+            Err(DecodeError("missing field 'value'".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut engine = Engine::new();
+        for src in [
+            "1 $ 2",
+            "\"abc",
+            "\"a\\qb\"",
+            "-\"a\"",
+            "\"a\" * 2",
+            "format(1)",
+            "99999999999999999999",
+            "1 +",
+            "(1 + 2",
+            "1 2",
+            "1 / 0",
+            "x",
+            "1 m + 1 s",
+            "[1, 2] * [3, 4]",
+            "det([1, 2])",
+            "transpose(1 + 2i)",
+            "1 + [1, 2] * 2i",
+            "sqrt(1, 2)",
+        ] {
+            round_trip(&engine.eval(src).unwrap_err());
+        }
+        round_trip(&Error::Codegen(CodegenError::new(
+            CodegenErrorKind::Unsupported("float"),
+            Loc(4, 7),
+        )));
+    }
+}
@@ -0,0 +1,254 @@
+use std::fmt;
+
+use crate::json::Json;
+
+// S 式。記号や数値のアトム、文字列、リストからなる
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sexpr {
+    Atom(String),
+    Str(String),
+    List(Vec<Sexpr>),
+}
+
+// S 式の読み込みや JSON との変換に失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SexprError {
+    pub pos: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for SexprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.pos)
+    }
+}
+
+impl std::error::Error for SexprError {}
+
+fn is_number(atom: &str) -> bool {
+    let digits = atom.strip_prefix('-').unwrap_or(atom);
+    digits.starts_with(|c: char| c.is_ascii_digit())
+}
+
+// 記号で始まる配列につける印。: で始まるのでオブジェクトの kind にはならない
+const ARRAY: &str = ":array";
+
+// オブジェクトの kind としてそのまま書ける記号か。_ は kind のないオブジェクトに使う
+fn is_symbol(kind: &str) -> bool {
+    !kind.is_empty()
+        && kind != "_"
+        && !is_number(kind)
+        && !kind.starts_with(':')
+        && !kind.contains(|c: char| c.is_whitespace() || "()\"".contains(c))
+}
+
+impl Sexpr {
+    pub fn parse(input: &str) -> Result<Sexpr, SexprError> {
+        let input = input.as_bytes();
+        let (s, pos) = parse_sexpr(input, skip_spaces(input, 0))?;
+        let pos = skip_spaces(input, pos);
+        if pos < input.len() {
+            return Err(SexprError {
+                pos,
+                message: "trailing characters",
+            });
+        }
+        Ok(s)
+    }
+
+    // JSON の値を S 式で表す
+    // オブジェクトは "kind" を先頭の記号にして (kind :key value ...)、配列は (a b c) になる
+    // null や true で始まる配列は記号で始まるオブジェクトと区別するため (:array null 1) と書く
+    pub fn from_json(json: &Json) -> Sexpr {
+        match json {
+            Json::Null => Sexpr::Atom("null".to_string()),
+            Json::Bool(b) => Sexpr::Atom(b.to_string()),
+            Json::Number(_) => Sexpr::Atom(json.to_string()),
+            Json::String(s) => Sexpr::Str(s.clone()),
+            Json::Array(items) => {
+                let mut list: Vec<Sexpr> = items.iter().map(Sexpr::from_json).collect();
+                if matches!(list.first(), Some(Sexpr::Atom(a)) if !is_number(a)) {
+                    list.insert(0, Sexpr::Atom(ARRAY.to_string()));
+                }
+                Sexpr::List(list)
+            }
+            Json::Object(fields) => {
+                // "kind" がない、または記号として書けないオブジェクトは _ で表し、kind もフィールドに残す
+                let kind = json
+                    .get("kind")
+                    .and_then(Json::as_str)
+                    .filter(|k| is_symbol(k));
+                let mut list = vec![Sexpr::Atom(kind.unwrap_or("_").to_string())];
+                for (k, v) in fields.iter().filter(|(k, _)| kind.is_none() || k != "kind") {
+                    list.push(Sexpr::Atom(format!(":{}", k)));
+                    list.push(Sexpr::from_json(v));
+                }
+                Sexpr::List(list)
+            }
+        }
+    }
+
+    // from_json の逆
+    pub fn to_json(&self) -> Result<Json, SexprError> {
+        let error = |message| SexprError { pos: 0, message };
+        match self {
+            Sexpr::Str(s) => Ok(Json::String(s.clone())),
+            Sexpr::Atom(a) if is_number(a) => a
+                .parse()
+                .map(Json::Number)
+                .map_err(|_| error("invalid number")),
+            Sexpr::Atom(a) => match a.as_str() {
+                "null" => Ok(Json::Null),
+                "true" => Ok(Json::Bool(true)),
+                "false" => Ok(Json::Bool(false)),
+                _ => Err(error("unexpected symbol")),
+            },
+            // 記号で始まるリストはオブジェクト
+            Sexpr::List(items) => match items.split_first() {
+                Some((Sexpr::Atom(a), rest)) if a == ARRAY => Ok(Json::Array(
+                    rest.iter().map(Sexpr::to_json).collect::<Result<_, _>>()?,
+                )),
+                Some((Sexpr::Atom(kind), rest)) if !is_number(kind) && !kind.starts_with(':') => {
+                    let mut fields = vec![];
+                    if kind != "_" {
+                        fields.push(("kind".to_string(), Json::from(kind.as_str())));
+                    }
+                    for pair in rest.chunks(2) {
+                        match pair {
+                            [Sexpr::Atom(k), v] if k.starts_with(':') => {
+                                fields.push((k[1..].to_string(), v.to_json()?));
+                            }
+                            _ => return Err(error("expected ':key value'")),
+                        }
+                    }
+                    Ok(Json::Object(fields))
+                }
+                _ => Ok(Json::Array(
+                    items.iter().map(Sexpr::to_json).collect::<Result<_, _>>()?,
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Sexpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sexpr::Atom(a) => write!(f, "{}", a),
+            // 文字列のエスケープは JSON と同じ
+            Sexpr::Str(s) => write!(f, "{}", Json::from(s.as_str())),
+            Sexpr::List(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+fn skip_spaces(input: &[u8], mut pos: usize) -> usize {
+    while pos < input.len() && input[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+fn parse_sexpr(input: &[u8], pos: usize) -> Result<(Sexpr, usize), SexprError> {
+    match input.get(pos) {
+        None => Err(SexprError {
+            pos,
+            message: "unexpected end of input",
+        }),
+        Some(b'(') => {
+            let mut items = vec![];
+            let mut pos = skip_spaces(input, pos + 1);
+            while input.get(pos) != Some(&b')') {
+                let (item, p) = parse_sexpr(input, pos)?;
+                items.push(item);
+                pos = skip_spaces(input, p);
+            }
+            Ok((Sexpr::List(items), pos + 1))
+        }
+        Some(b')') => Err(SexprError {
+            pos,
+            message: "unexpected ')'",
+        }),
+        Some(b'"') => {
+            // 文字列は JSON の文字列として読む
+            let mut end = pos + 1;
+            while end < input.len() && input[end] != b'"' {
+                end += if input[end] == b'\\' { 2 } else { 1 };
+            }
+            let error = SexprError {
+                pos,
+                message: "invalid string",
+            };
+            let text = input.get(pos..end + 1).ok_or(error.clone())?;
+            let text = std::str::from_utf8(text).map_err(|_| error.clone())?;
+            match Json::parse(text) {
+                Ok(Json::String(s)) => Ok((Sexpr::Str(s), end + 1)),
+                _ => Err(error),
+            }
+        }
+        Some(_) => {
+            let mut end = pos;
+            while end < input.len()
+                && !input[end].is_ascii_whitespace()
+                && !b"()\"".contains(&input[end])
+            {
+                end += 1;
+            }
+            // 区切り文字以外は ASCII とは限らないので、文字列として切り出す
+            let atom = String::from_utf8_lossy(&input[pos..end]).into_owned();
+            Ok((Sexpr::Atom(atom), end))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sexpr() {
+        let src = r#"(binop :loc (0 5) :name "a \"b\"" :args () :ok true)"#;
+        let s = Sexpr::parse(src).unwrap();
+        assert_eq!(s.to_string(), src);
+        let json = s.to_json().unwrap();
+        assert_eq!(
+            json.to_string(),
+            r#"{"kind":"binop","loc":[0,5],"name":"a \"b\"","args":[],"ok":true}"#
+        );
+        assert_eq!(Sexpr::from_json(&json), s);
+        assert_eq!(
+            Sexpr::parse("(a"),
+            Err(SexprError {
+                pos: 2,
+                message: "unexpected end of input"
+            })
+        );
+        assert!(Sexpr::parse("(a :b)").unwrap().to_json().is_err());
+
+        // 記号で始まる配列や、記号として書けない kind も元に戻る
+        for src in [
+            r#"[true, 1]"#,
+            r#"[null, [false], {"kind":"true"}]"#,
+            r#"{"kind":"a b","x":[]}"#,
+            r#"{"kind":1}"#,
+            r#"{"kind":"_"}"#,
+        ] {
+            let json = Json::parse(src).unwrap();
+            let s = Sexpr::from_json(&json);
+            assert_eq!(Sexpr::parse(&s.to_string()).unwrap().to_json(), Ok(json));
+        }
+        assert_eq!(
+            Sexpr::from_json(&Json::parse("[true, 1]").unwrap()).to_string(),
+            "(:array true 1)"
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+
+use crate::{Error, Loc};
+
+// SourceMap に登録したソースの番号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(usize);
+
+// 登録したソース1つ分。start はすべてのソースを並べた位置空間での先頭の位置
+#[derive(Debug)]
+struct SourceFile {
+    name: String,
+    src: String,
+    start: usize,
+    // 各行の先頭の位置（ファイルの中でのバイト位置）
+    line_starts: Vec<usize>,
+}
+
+// 位置を人が読める形にしたもの。行と列は 1 から数え、列は文字単位
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub file: FileId,
+    pub name: &'a str,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.name, self.line, self.col)
+    }
+}
+
+// 複数のソース（REPL の入力、スクリプトファイル、-e で渡した式）をまとめて持つ
+// ソースごとに重ならない位置の範囲を割り当てるので、Loc だけでどのソースのどこかがわかる
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, src: impl Into<String>) -> FileId {
+        let src = src.into();
+        // 末尾（入力の終わり）の位置も区別できるように1つ空けて並べる
+        let start = self.files.last().map_or(0, |f| f.start + f.src.len() + 1);
+        let mut line_starts = vec![0];
+        line_starts.extend(src.match_indices('\n').map(|(i, _)| i + 1));
+        self.files.push(SourceFile {
+            name: name.into(),
+            src,
+            start,
+            line_starts,
+        });
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn name(&self, id: FileId) -> &str {
+        &self.files[id.0].name
+    }
+
+    pub fn source(&self, id: FileId) -> &str {
+        &self.files[id.0].src
+    }
+
+    // ソース全体の範囲
+    pub fn span(&self, id: FileId) -> Loc {
+        let f = &self.files[id.0];
+        Loc(f.start, f.start + f.src.len())
+    }
+
+    // 各行の範囲（改行は含まない）
+    pub fn lines(&self, id: FileId) -> Vec<Loc> {
+        let f = &self.files[id.0];
+        (0..f.line_starts.len())
+            .map(|line| {
+                let (s, e) = f.line_range(line);
+                Loc(f.start + s, f.start + e)
+            })
+            .collect()
+    }
+
+    // 範囲の文字列
+    pub fn snippet(&self, loc: &Loc) -> &str {
+        let f = self.file_at(loc.0);
+        &f.src[loc.0 - f.start..loc.1 - f.start]
+    }
+
+    // 位置を含むソース
+    pub fn file_id(&self, pos: usize) -> FileId {
+        FileId(self.files.partition_point(|f| f.start <= pos) - 1)
+    }
+
+    fn file_at(&self, pos: usize) -> &SourceFile {
+        &self.files[self.file_id(pos).0]
+    }
+
+    pub fn resolve(&self, pos: usize) -> Location<'_> {
+        let id = self.file_id(pos);
+        let f = &self.files[id.0];
+        let offset = pos - f.start;
+        let line = f.line_starts.partition_point(|&s| s <= offset) - 1;
+        let col = f.src[f.line_starts[line]..offset].chars().count() + 1;
+        Location {
+            file: id,
+            name: &f.name,
+            line: line + 1,
+            col,
+        }
+    }
+
+    // エラーの位置を "file:line:col" で示し、その行の下にエラー箇所を ^^^ で示した文字列を返す
+    // span は評価した範囲で、位置のないエラー（入力の終わり）は span の終わりを指す
+    pub fn show_diagnostic(&self, e: &Error, span: &Loc) -> String {
+        let loc = match e.loc() {
+            Some(loc) => loc.clone(),
+            None => Loc(span.1, span.1 + 1),
+        };
+        let f = self.file_at(loc.0);
+        let at = self.resolve(loc.0);
+        let (s, end) = f.line_range(at.line - 1);
+        let line = &f.src[s..end];
+        // 行をまたぐ範囲は行末までにする
+        let width = f.src[loc.0 - f.start..(loc.1 - f.start).min(end).max(loc.0 - f.start)]
+            .chars()
+            .count()
+            .max(1);
+        format!(
+            "{}: {}\n{}\n{}{}\n",
+            at,
+            e.kind_message(),
+            line,
+            " ".repeat(at.col - 1),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl SourceFile {
+    fn line_range(&self, line: usize) -> (usize, usize) {
+        let s = self.line_starts[line];
+        let e = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.src.len(), |&next| next - 1);
+        // CRLF の '\r' も改行に含める
+        let e = if self.src[s..e].ends_with('\r') {
+            e - 1
+        } else {
+            e
+        };
+        (s, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Engine;
+
+    #[test]
+    fn test_resolve() {
+        let mut map = SourceMap::new();
+        let repl = map.add("<repl:1>", "1 + 2");
+        let script = map.add("script.calc", "1\n\n2 * (3 + é)\n4");
+        assert_eq!(map.span(repl), Loc(0, 5));
+        assert_eq!(map.span(script), Loc(6, 23));
+        assert_eq!(
+            map.lines(script),
+            vec![Loc(6, 7), Loc(8, 8), Loc(9, 21), Loc(22, 23)]
+        );
+        assert_eq!(map.snippet(&Loc(9, 10)), "2");
+        assert_eq!(map.resolve(4).to_string(), "<repl:1>:1:5");
+        assert_eq!(map.resolve(5).to_string(), "<repl:1>:1:6");
+        assert_eq!(map.resolve(22).to_string(), "script.calc:4:1");
+        // é は2バイトだが1列と数える
+        assert_eq!(map.resolve(20).to_string(), "script.calc:3:11");
+        assert_eq!(map.file_id(22), script);
+    }
+
+    #[test]
+    fn test_crlf() {
+        let mut map = SourceMap::new();
+        let script = map.add("script.calc", "1 + 2\r\n\r\nx * 3\r\n");
+        let lines = map.lines(script);
+        assert_eq!(lines, vec![Loc(0, 5), Loc(7, 7), Loc(9, 14), Loc(16, 16)]);
+        assert_eq!(map.snippet(&lines[2]), "x * 3");
+
+        let mut engine = Engine::new();
+        assert_eq!(engine.eval_span(&map, &lines[0]), Ok(crate::Value::Int(3)));
+        engine.set_var("x", 2).unwrap();
+        assert_eq!(engine.eval_span(&map, &lines[2]), Ok(crate::Value::Int(6)));
+
+        // 行をまたぐ式の '\r' は空白として読み飛ばす
+        let multi = map.add("multi.calc", "(1 +\r\n 2) * 3\r\n");
+        assert_eq!(
+            engine.eval_span(&map, &map.span(multi)),
+            Ok(crate::Value::Int(9))
+        );
+    }
+
+    #[test]
+    fn test_show_diagnostic() {
+        let mut map = SourceMap::new();
+        map.add("-e", "1");
+        let script = map.add("script.calc", "1 + 2\nx * 3\n(1 +");
+        let mut engine = Engine::new();
+        let lines = map.lines(script);
+        assert_eq!(engine.eval_span(&map, &lines[0]), Ok(crate::Value::Int(3)));
+
+        let e = engine.eval_span(&map, &lines[1]).unwrap_err();
+        assert_eq!(
+            map.show_diagnostic(&e, &lines[1]),
+            "script.calc:2:1: interpreter error: variable 'x' is not defined\nx * 3\n^\n"
+        );
+        let e = engine.eval_span(&map, &lines[2]).unwrap_err();
+        assert_eq!(
+            map.show_diagnostic(&e, &lines[2]),
+            "script.calc:3:5: parser error: End of file\n(1 +\n    ^\n"
+        );
+    }
+}
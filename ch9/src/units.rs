@@ -0,0 +1,265 @@
+use std::fmt;
+
+// 次元。SI 基本単位 (m, kg, s, A, K, mol, cd) それぞれの指数を持つ
+// 例えば速度 m/s は [1, 0, -1, 0, 0, 0, 0]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Dim(pub [i8; 7]);
+
+const BASE_SYMBOLS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+impl Dim {
+    pub const NONE: Dim = Dim([0; 7]);
+    pub const LENGTH: Dim = Dim([1, 0, 0, 0, 0, 0, 0]);
+    pub const MASS: Dim = Dim([0, 1, 0, 0, 0, 0, 0]);
+    pub const TIME: Dim = Dim([0, 0, 1, 0, 0, 0, 0]);
+    pub const CURRENT: Dim = Dim([0, 0, 0, 1, 0, 0, 0]);
+    pub const TEMPERATURE: Dim = Dim([0, 0, 0, 0, 1, 0, 0]);
+    pub const AMOUNT: Dim = Dim([0, 0, 0, 0, 0, 1, 0]);
+    pub const LUMINOSITY: Dim = Dim([0, 0, 0, 0, 0, 0, 1]);
+
+    pub fn is_none(&self) -> bool {
+        *self == Dim::NONE
+    }
+
+    // 以下の演算は、指数が i8 に収まらなければ None を返す
+    pub fn powi(&self, n: i8) -> Option<Dim> {
+        let mut d = *self;
+        for e in d.0.iter_mut() {
+            *e = e.checked_mul(n)?;
+        }
+        Some(d)
+    }
+
+    // 次元の掛け算は指数の足し算、割り算は指数の引き算になる
+    pub fn mul(&self, other: &Dim) -> Option<Dim> {
+        let mut d = *self;
+        for (e, o) in d.0.iter_mut().zip(other.0.iter()) {
+            *e = e.checked_add(*o)?;
+        }
+        Some(d)
+    }
+
+    pub fn div(&self, other: &Dim) -> Option<Dim> {
+        let mut d = *self;
+        for (e, o) in d.0.iter_mut().zip(other.0.iter()) {
+            *e = e.checked_sub(*o)?;
+        }
+        Some(d)
+    }
+}
+
+impl fmt::Display for Dim {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_none() {
+            return write!(f, "1");
+        }
+        // 正の指数を分子、負の指数を分母に並べる（kg*m/s2 のような形）
+        let term = |sym: &str, e: i8| {
+            if e == 1 {
+                sym.to_string()
+            } else {
+                format!("{}{}", sym, e)
+            }
+        };
+        let num: Vec<String> = (0..7)
+            .filter(|&i| self.0[i] > 0)
+            .map(|i| term(BASE_SYMBOLS[i], self.0[i]))
+            .collect();
+        let den: Vec<String> = (0..7)
+            .filter(|&i| self.0[i] < 0)
+            .map(|i| term(BASE_SYMBOLS[i], -self.0[i]))
+            .collect();
+        let num = if num.is_empty() {
+            "1".to_string()
+        } else {
+            num.join("*")
+        };
+        if den.is_empty() {
+            write!(f, "{}", num)
+        } else {
+            write!(f, "{}/{}", num, den.join("/"))
+        }
+    }
+}
+
+// 単位。1 name = factor [SI 基本単位] で、次元 dim を持つ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub name: String,
+    pub factor: f64,
+    pub dim: Dim,
+}
+
+impl Unit {
+    pub fn new(name: impl Into<String>, factor: f64, dim: Dim) -> Self {
+        Unit {
+            name: name.into(),
+            factor,
+            dim,
+        }
+    }
+
+    // 単位同士の掛け算・割り算。表示名は演算子でつなげる。次元の指数が溢れたら None
+    pub fn mul(&self, other: &Unit) -> Option<Unit> {
+        Some(Unit::new(
+            format!("{}*{}", self.name, other.paren_name()),
+            self.factor * other.factor,
+            self.dim.mul(&other.dim)?,
+        ))
+    }
+
+    pub fn div(&self, other: &Unit) -> Option<Unit> {
+        Some(Unit::new(
+            format!("{}/{}", self.name, other.paren_name()),
+            self.factor / other.factor,
+            self.dim.div(&other.dim)?,
+        ))
+    }
+
+    // 右辺に来たときに、複合単位なら括弧でくくる
+    fn paren_name(&self) -> String {
+        if self.name.contains(['*', '/']) {
+            format!("({})", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+// (記号, SI 換算係数, 次元, SI 接頭辞をつけられるか)
+const UNITS: &[(&str, f64, Dim, bool)] = &[
+    // 基本単位（質量はグラムを基準にして接頭辞 k をつける）
+    ("m", 1.0, Dim::LENGTH, true),
+    ("g", 1e-3, Dim::MASS, true),
+    ("s", 1.0, Dim::TIME, true),
+    ("A", 1.0, Dim::CURRENT, true),
+    ("K", 1.0, Dim::TEMPERATURE, true),
+    ("mol", 1.0, Dim::AMOUNT, true),
+    ("cd", 1.0, Dim::LUMINOSITY, true),
+    // 組立単位
+    ("Hz", 1.0, Dim([0, 0, -1, 0, 0, 0, 0]), true),
+    ("N", 1.0, Dim([1, 1, -2, 0, 0, 0, 0]), true),
+    ("Pa", 1.0, Dim([-1, 1, -2, 0, 0, 0, 0]), true),
+    ("J", 1.0, Dim([2, 1, -2, 0, 0, 0, 0]), true),
+    ("W", 1.0, Dim([2, 1, -3, 0, 0, 0, 0]), true),
+    ("C", 1.0, Dim([0, 0, 1, 1, 0, 0, 0]), true),
+    ("V", 1.0, Dim([2, 1, -3, -1, 0, 0, 0]), true),
+    ("Ohm", 1.0, Dim([2, 1, -3, -2, 0, 0, 0]), true),
+    ("L", 1e-3, Dim([3, 0, 0, 0, 0, 0, 0]), true),
+    // SI 以外でよく使うもの
+    ("min", 60.0, Dim::TIME, false),
+    ("h", 3600.0, Dim::TIME, false),
+    ("day", 86400.0, Dim::TIME, false),
+    ("ft", 0.3048, Dim::LENGTH, false),
+    ("mi", 1609.344, Dim::LENGTH, false),
+    ("lb", 0.45359237, Dim::MASS, false),
+];
+
+const PREFIXES: &[(&str, f64)] = &[
+    ("da", 1e1),
+    ("p", 1e-12),
+    ("n", 1e-9),
+    ("u", 1e-6),
+    ("m", 1e-3),
+    ("c", 1e-2),
+    ("d", 1e-1),
+    ("h", 1e2),
+    ("k", 1e3),
+    ("M", 1e6),
+    ("G", 1e9),
+    ("T", 1e12),
+];
+
+fn lookup_simple(name: &str) -> Option<Unit> {
+    // 完全一致を優先する（min は m + in ではなく分、cd は c + d ではなくカンデラ）
+    if let Some(&(sym, factor, dim, _)) = UNITS.iter().find(|u| u.0 == name) {
+        return Some(Unit::new(sym, factor, dim));
+    }
+    PREFIXES.iter().find_map(|&(prefix, scale)| {
+        let rest = name.strip_prefix(prefix)?;
+        UNITS
+            .iter()
+            .find(|u| u.0 == rest && u.3)
+            .map(|&(_, factor, dim, _)| Unit::new(name, scale * factor, dim))
+    })
+}
+
+// 単位名を探す。末尾の数字は指数として扱う（m2 は平方メートル、s2 は秒の2乗）
+pub fn lookup(name: &str) -> Option<Unit> {
+    let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return lookup_simple(name);
+    }
+    let (base, power) = name.split_at(name.len() - digits);
+    let power = power.parse::<i8>().ok()?;
+    let unit = lookup_simple(base)?;
+    Some(Unit::new(
+        name,
+        unit.factor.powi(power as i32),
+        unit.dim.powi(power)?,
+    ))
+}
+
+// 物理量。value は SI 基本単位での値、unit は表示に使う単位
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub dim: Dim,
+    pub unit: Option<Unit>,
+}
+
+impl Quantity {
+    // n [unit] を表す量
+    pub fn new(n: f64, unit: Unit) -> Self {
+        Quantity {
+            value: n * unit.factor,
+            dim: unit.dim,
+            unit: Some(unit),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.unit {
+            Some(unit) => write!(f, "{} {}", self.value / unit.factor, unit.name),
+            None => write!(f, "{} {}", self.value, self.dim),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup("m"), Some(Unit::new("m", 1.0, Dim::LENGTH)));
+        assert_eq!(lookup("km"), Some(Unit::new("km", 1e3, Dim::LENGTH)));
+        assert_eq!(lookup("kg"), Some(Unit::new("kg", 1.0, Dim::MASS)));
+        assert_eq!(lookup("ms"), Some(Unit::new("ms", 1e-3, Dim::TIME)));
+        assert_eq!(lookup("min"), Some(Unit::new("min", 60.0, Dim::TIME)));
+        assert_eq!(lookup("cm2").unwrap().dim, Dim::LENGTH.powi(2).unwrap());
+        assert_eq!(lookup("kh"), None);
+        // 指数が i8 に収まらない単位はない
+        assert_eq!(lookup("N100"), None);
+        assert_eq!(lookup("foo"), None);
+    }
+
+    #[test]
+    fn test_dim_overflow() {
+        let m100 = Dim::LENGTH.powi(100).unwrap();
+        assert_eq!(m100.mul(&m100), None);
+        assert_eq!(Dim::NONE.div(&m100).unwrap().div(&m100), None);
+        assert_eq!(Dim::LENGTH.powi(-128).unwrap().powi(-1), None);
+    }
+
+    #[test]
+    fn test_dim_display() {
+        assert_eq!(Dim::LENGTH.to_string(), "m");
+        assert_eq!(Dim::LENGTH.div(&Dim::TIME).unwrap().to_string(), "m/s");
+        assert_eq!(lookup("N").unwrap().dim.to_string(), "m*kg/s2");
+        assert_eq!(Dim::NONE.to_string(), "1");
+        assert_eq!(Dim([0, 0, -1, 0, 0, 0, 0]).to_string(), "1/s");
+    }
+}
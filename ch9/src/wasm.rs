@@ -0,0 +1,361 @@
+use std::fmt::Write;
+
+use crate::asm::{unsupported, CodegenError, CodegenErrorKind};
+use crate::parser::{Ast, AstKind, BinOpKind, UniOpKind};
+
+// WebAssembly のモジュールに変換するバックエンド
+// 引数なしで式の値を返す関数を "eval" という名前でエクスポートする
+
+// 値の型。整数モードでは i64、実数モードでは f64 で計算する
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasmMode {
+    I64,
+    F64,
+}
+
+impl WasmMode {
+    fn name(self) -> &'static str {
+        match self {
+            WasmMode::I64 => "i64",
+            WasmMode::F64 => "f64",
+        }
+    }
+
+    // 値型のバイナリ表現
+    fn valtype(self) -> u8 {
+        match self {
+            WasmMode::I64 => 0x7e,
+            WasmMode::F64 => 0x7c,
+        }
+    }
+}
+
+// スタックマシンの命令。テキストとバイナリの両方をここから作る
+#[derive(Debug, Clone, PartialEq)]
+enum Instr {
+    I64Const(i64),
+    F64Const(f64),
+    // 以下は mode の型で計算する
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    // 以下は i64 だけ
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+}
+
+fn lower(ast: &Ast, mode: WasmMode, code: &mut Vec<Instr>) -> Result<(), CodegenError> {
+    use self::AstKind::*;
+    match (&ast.value, mode) {
+        (Num(n), WasmMode::I64) => code
+            .push(Instr::I64Const(i64::try_from(*n).map_err(|_| {
+                CodegenError::new(CodegenErrorKind::Overflow, ast.loc.clone())
+            })?)),
+        (Num(n), WasmMode::F64) => code.push(Instr::F64Const(*n as f64)),
+        (Float(x), WasmMode::F64) => code.push(Instr::F64Const(*x)),
+        (Float(_), WasmMode::I64) => return Err(unsupported("float", &ast.loc)),
+        (UniOp { op, e }, _) => match op.value {
+            UniOpKind::Plus => lower(e, mode, code)?,
+            // i64 には符号反転の命令がないので 0 - e にする
+            UniOpKind::Minus if mode == WasmMode::I64 => {
+                code.push(Instr::I64Const(0));
+                lower(e, mode, code)?;
+                code.push(Instr::Sub);
+            }
+            UniOpKind::Minus => {
+                lower(e, mode, code)?;
+                code.push(Instr::Neg);
+            }
+            // ビット反転は -1 との xor
+            UniOpKind::Not if mode == WasmMode::I64 => {
+                lower(e, mode, code)?;
+                code.push(Instr::I64Const(-1));
+                code.push(Instr::Xor);
+            }
+            UniOpKind::Not => return Err(unsupported("bitwise operator", &op.loc)),
+        },
+        (BinOp { op, .. }, WasmMode::F64) if op.value.is_bitwise() => {
+            return Err(unsupported("bitwise operator", &op.loc))
+        }
+        (BinOp { op, l, r }, _) => {
+            lower(l, mode, code)?;
+            lower(r, mode, code)?;
+            code.push(match op.value {
+                BinOpKind::Add => Instr::Add,
+                BinOpKind::Sub => Instr::Sub,
+                BinOpKind::Mult => Instr::Mul,
+                BinOpKind::Div => Instr::Div,
+                BinOpKind::BitAnd => Instr::And,
+                BinOpKind::BitOr => Instr::Or,
+                BinOpKind::BitXor => Instr::Xor,
+                BinOpKind::Shl => Instr::Shl,
+                BinOpKind::Shr => Instr::Shr,
+            });
+        }
+        (Imaginary(_), _) => return Err(unsupported("complex number", &ast.loc)),
+        (Str(_), _) => return Err(unsupported("string", &ast.loc)),
+        (Matrix(_), _) => return Err(unsupported("matrix", &ast.loc)),
+        (Var(_), _) => return Err(unsupported("variable", &ast.loc)),
+        (WithUnit { .. } | Convert { .. }, _) => return Err(unsupported("unit", &ast.loc)),
+        (Call { .. }, _) => return Err(unsupported("function call", &ast.loc)),
+    }
+    Ok(())
+}
+
+fn instrs(ast: &Ast, mode: WasmMode) -> Result<Vec<Instr>, CodegenError> {
+    let mut code = vec![];
+    lower(ast, mode, &mut code)?;
+    Ok(code)
+}
+
+// テキスト形式 (.wat)
+// 整数の割り算は i64.div_s で、0 で割ると実行時にトラップする
+// シフト量は WebAssembly の仕様どおり 64 で割った余りになる（インタプリタでは桁溢れ）
+pub fn compile_wat(ast: &Ast, mode: WasmMode) -> Result<String, CodegenError> {
+    let ty = mode.name();
+    let mut out = String::new();
+    writeln!(out, "(module").unwrap();
+    write!(out, "  (func (export \"eval\") (result {})", ty).unwrap();
+    for instr in instrs(ast, mode)? {
+        out.push_str("\n    ");
+        match instr {
+            Instr::I64Const(n) => write!(out, "i64.const {}", n).unwrap(),
+            Instr::F64Const(x) => write!(out, "f64.const {:?}", x).unwrap(),
+            Instr::Add => write!(out, "{}.add", ty).unwrap(),
+            Instr::Sub => write!(out, "{}.sub", ty).unwrap(),
+            Instr::Mul => write!(out, "{}.mul", ty).unwrap(),
+            Instr::Div if mode == WasmMode::I64 => write!(out, "i64.div_s").unwrap(),
+            Instr::Div => write!(out, "f64.div").unwrap(),
+            Instr::Neg => write!(out, "f64.neg").unwrap(),
+            Instr::And => write!(out, "i64.and").unwrap(),
+            Instr::Or => write!(out, "i64.or").unwrap(),
+            Instr::Xor => write!(out, "i64.xor").unwrap(),
+            Instr::Shl => write!(out, "i64.shl").unwrap(),
+            Instr::Shr => write!(out, "i64.shr_s").unwrap(),
+        }
+    }
+    writeln!(out, ")").unwrap();
+    writeln!(out, ")").unwrap();
+    Ok(out)
+}
+
+// 符号なし LEB128。長さや個数に使う
+fn uleb128(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+// 符号つき LEB128。i64.const の即値に使う
+fn sleb128(out: &mut Vec<u8>, mut n: i64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        // 残りが符号の広がりだけで、最後のバイトの符号ビットが合っていれば終わり
+        if (n == 0 && byte & 0x40 == 0) || (n == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+// セクション。id と中身の長さを前につける
+fn section(out: &mut Vec<u8>, id: u8, content: &[u8]) {
+    out.push(id);
+    uleb128(out, content.len() as u64);
+    out.extend_from_slice(content);
+}
+
+// バイナリ形式 (.wasm)
+pub fn compile_wasm(ast: &Ast, mode: WasmMode) -> Result<Vec<u8>, CodegenError> {
+    // 関数本体: ローカル変数の宣言の数、命令列、end
+    let mut body = vec![0x00];
+    for instr in instrs(ast, mode)? {
+        match (instr, mode) {
+            (Instr::I64Const(n), _) => {
+                body.push(0x42);
+                sleb128(&mut body, n);
+            }
+            (Instr::F64Const(x), _) => {
+                body.push(0x44);
+                body.extend_from_slice(&x.to_le_bytes());
+            }
+            (Instr::Add, WasmMode::I64) => body.push(0x7c),
+            (Instr::Sub, WasmMode::I64) => body.push(0x7d),
+            (Instr::Mul, WasmMode::I64) => body.push(0x7e),
+            (Instr::Div, WasmMode::I64) => body.push(0x7f),
+            (Instr::And, _) => body.push(0x83),
+            (Instr::Or, _) => body.push(0x84),
+            (Instr::Xor, _) => body.push(0x85),
+            (Instr::Shl, _) => body.push(0x86),
+            (Instr::Shr, _) => body.push(0x87),
+            (Instr::Neg, WasmMode::F64) => body.push(0x9a),
+            (Instr::Add, WasmMode::F64) => body.push(0xa0),
+            (Instr::Sub, WasmMode::F64) => body.push(0xa1),
+            (Instr::Mul, WasmMode::F64) => body.push(0xa2),
+            (Instr::Div, WasmMode::F64) => body.push(0xa3),
+            (Instr::Neg, WasmMode::I64) => unreachable!("i64 has no neg instruction"),
+        }
+    }
+    body.push(0x0b);
+
+    let mut out = b"\0asm".to_vec();
+    out.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+    // 型: () -> mode の関数型が1つ
+    section(&mut out, 1, &[0x01, 0x60, 0x00, 0x01, mode.valtype()]);
+    // 関数: 0 番目の型を使う関数が1つ
+    section(&mut out, 3, &[0x01, 0x00]);
+    // エクスポート: 0 番目の関数を "eval" として
+    section(
+        &mut out,
+        7,
+        &[0x01, 0x04, b'e', b'v', b'a', b'l', 0x00, 0x00],
+    );
+    // コード
+    let mut code = vec![0x01];
+    uleb128(&mut code, body.len() as u64);
+    code.extend_from_slice(&body);
+    section(&mut out, 10, &code);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex, parse, Loc};
+
+    fn ast(src: &str) -> Ast {
+        parse(lex(src).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_leb128() {
+        let u = |n| {
+            let mut v = vec![];
+            uleb128(&mut v, n);
+            v
+        };
+        let s = |n| {
+            let mut v = vec![];
+            sleb128(&mut v, n);
+            v
+        };
+        assert_eq!(u(0), vec![0x00]);
+        assert_eq!(u(127), vec![0x7f]);
+        assert_eq!(u(624485), vec![0xe5, 0x8e, 0x26]);
+        assert_eq!(s(2), vec![0x02]);
+        assert_eq!(s(-1), vec![0x7f]);
+        assert_eq!(s(63), vec![0x3f]);
+        assert_eq!(s(64), vec![0xc0, 0x00]);
+        assert_eq!(s(-65), vec![0xbf, 0x7f]);
+        assert_eq!(s(-123456), vec![0xc0, 0xbb, 0x78]);
+        assert_eq!(s(i64::MIN).len(), 10);
+    }
+
+    #[test]
+    fn test_wat() {
+        assert_eq!(
+            compile_wat(&ast("-(1 + 2) / 3"), WasmMode::I64).unwrap(),
+            "(module
+  (func (export \"eval\") (result i64)
+    i64.const 0
+    i64.const 1
+    i64.const 2
+    i64.add
+    i64.sub
+    i64.const 3
+    i64.div_s)
+)
+"
+        );
+        assert_eq!(
+            compile_wat(&ast("-2.5 * 4"), WasmMode::F64).unwrap(),
+            "(module
+  (func (export \"eval\") (result f64)
+    f64.const 2.5
+    f64.neg
+    f64.const 4.0
+    f64.mul)
+)
+"
+        );
+        assert_eq!(
+            compile_wat(&ast("~1 & 6 >> 1"), WasmMode::I64).unwrap(),
+            "(module
+  (func (export \"eval\") (result i64)
+    i64.const 1
+    i64.const -1
+    i64.xor
+    i64.const 6
+    i64.const 1
+    i64.shr_s
+    i64.and)
+)
+"
+        );
+        assert_eq!(
+            compile_wat(&ast("1.5 | 2"), WasmMode::F64),
+            Err(unsupported("bitwise operator", &Loc(4, 5)))
+        );
+        assert_eq!(
+            compile_wat(&ast("1 + 2.5"), WasmMode::I64),
+            Err(unsupported("float", &Loc(4, 7)))
+        );
+    }
+
+    #[test]
+    fn test_wasm() {
+        let header = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        let sections = |valtype: u8| {
+            vec![
+                0x01, 0x05, 0x01, 0x60, 0x00, 0x01, valtype, // type
+                0x03, 0x02, 0x01, 0x00, // function
+                0x07, 0x08, 0x01, 0x04, b'e', b'v', b'a', b'l', 0x00, 0x00, // export
+            ]
+        };
+
+        let mut expected = header.to_vec();
+        expected.extend(sections(0x7e));
+        expected.extend([
+            0x0a, 0x10, 0x01, 0x0e,
+            0x00, // code: 関数が1つ、本体 14 バイト、ローカル変数なし
+            0x42, 0x0a, // i64.const 10
+            0x42, 0xc0, 0x00, // i64.const 64
+            0x7d, // i64.sub
+            0x42, 0x00, // i64.const 0
+            0x42, 0x03, // i64.const 3
+            0x7d, // i64.sub
+            0x7e, // i64.mul
+            0x0b, // end
+        ]);
+        assert_eq!(
+            compile_wasm(&ast("(10 - 64) * -3"), WasmMode::I64),
+            Ok(expected)
+        );
+
+        let mut expected = header.to_vec();
+        expected.extend(sections(0x7c));
+        expected.extend([0x0a, 0x18, 0x01, 0x16, 0x00]);
+        expected.push(0x44);
+        expected.extend(1.5f64.to_le_bytes());
+        expected.push(0x44);
+        expected.extend(2.0f64.to_le_bytes());
+        expected.extend([0xa3, 0x9a, 0x0b]);
+        assert_eq!(
+            compile_wasm(&ast("-(1.5 / 2)"), WasmMode::F64),
+            Ok(expected)
+        );
+    }
+}
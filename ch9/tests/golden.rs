@@ -0,0 +1,106 @@
+// tests/cases/*.calc の各行を字句解析・構文解析・評価し、結果を .expected ファイルと比べる
+// CH9_BLESS=1 をつけて実行すると、今の出力で .expected を書き換える
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use ch9::{lex_at, parse, Engine, Error, Serialize, SourceMap};
+
+// 1つのファイルの出力。行ごとにトークン列、AST、値（またはエラー）を並べる
+fn run_case(name: &str, src: &str) -> String {
+    let mut map = SourceMap::new();
+    let id = map.add(name, src);
+    let mut engine = Engine::new();
+    let mut out = String::new();
+    for line in map.lines(id) {
+        let text = map.snippet(&line);
+        if text.trim().is_empty() {
+            continue;
+        }
+        writeln!(out, "== {}", text).unwrap();
+        let result = lex_at(text, line.0)
+            .map_err(Error::from)
+            .and_then(|tokens| {
+                writeln!(out, "tokens:").unwrap();
+                for tok in &tokens {
+                    writeln!(out, "  {}", tok.to_sexpr()).unwrap();
+                }
+                let ast = parse(tokens)?;
+                writeln!(out, "ast:").unwrap();
+                writeln!(out, "  {}", ast.to_sexpr()).unwrap();
+                engine.eval_span(&map, &line)
+            });
+        match result {
+            Ok(v) => writeln!(out, "value: {}", v).unwrap(),
+            Err(e) => {
+                writeln!(out, "error:").unwrap();
+                // ^^^ の位置がずれないよう、すべての行を同じ幅だけ字下げする
+                for l in map.show_diagnostic(&e, &line).lines() {
+                    writeln!(out, "  {}", l).unwrap();
+                }
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// 最初に食い違った行を示す
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for n in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => break,
+            (e, a) if e == a => continue,
+            (e, a) => {
+                return format!(
+                    "line {}:\n  expected: {}\n  actual:   {}",
+                    n,
+                    e.unwrap_or("<end of file>"),
+                    a.unwrap_or("<end of file>")
+                )
+            }
+        }
+    }
+    "trailing newline differs".to_string()
+}
+
+#[test]
+fn golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let bless = std::env::var_os("CH9_BLESS").is_some_and(|v| v != "0");
+
+    let mut cases: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "calc"))
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "no cases in {}", dir.display());
+
+    let mut failures = vec![];
+    for path in cases {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let actual = run_case(&name, &fs::read_to_string(&path).unwrap());
+        let expected_path = path.with_extension("expected");
+        if bless {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{}: {}",
+                name,
+                first_difference(&expected, &actual)
+            )),
+            Err(_) => failures.push(format!("{}: no .expected file", name)),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\n\nrun with CH9_BLESS=1 to update the expected files",
+        failures.join("\n")
+    );
+}